@@ -1,5 +1,5 @@
-import { useState, useEffect } from "react";
-import { render } from "mustache-wasm";
+import { useState, useEffect, useRef } from "react";
+import { Document } from "mustache-wasm";
 
 function useThrowableMemo(op, deps) {
   const [value, setValue] = useState(null);
@@ -18,13 +18,49 @@ function useThrowableMemo(op, deps) {
   return [value, error];
 }
 
+const isLowSurrogate = (unit) => unit >= 0xdc00 && unit <= 0xdfff;
+
+// Applies a change to the document as the one edit that turns its text into
+// `text`, so only the tokens around the edit are read again.
+function update(document, text) {
+  const before = document.text();
+  let start = 0;
+  while (start < before.length && start < text.length && before[start] === text[start]) {
+    start++;
+  }
+  // Keep surrogate pairs whole: an emoji swapped for another may share its
+  // high surrogate, or its low one.
+  if (start > 0 && isLowSurrogate(before.charCodeAt(start))) {
+    start--;
+  }
+  let end = 0;
+  while (
+    end < before.length - start &&
+    end < text.length - start &&
+    before[before.length - 1 - end] === text[text.length - 1 - end]
+  ) {
+    end++;
+  }
+  if (end > 0 && isLowSurrogate(before.charCodeAt(before.length - end))) {
+    end--;
+  }
+  if (start < before.length - end || start < text.length - end) {
+    document.edit(start, before.length - end, text.slice(start, text.length - end));
+  }
+}
+
 export function useMustache(template, data, partials) {
+  const document = useRef(null);
   const [context, jsonError] = useThrowableMemo(() => JSON.parse(data), [data]);
 
-  const [output, templateError] = useThrowableMemo(
-    () => render(template, context, (key) => partials[key] ?? ""),
-    [template, context, partials]
-  );
+  const [output, templateError] = useThrowableMemo(() => {
+    if (document.current === null) {
+      document.current = new Document(template);
+    } else {
+      update(document.current, template);
+    }
+    return document.current.render(context, (key) => partials[key] ?? "");
+  }, [template, context, partials]);
 
   return [output, jsonError ?? templateError ?? null];
 }
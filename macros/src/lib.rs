@@ -1,6 +1,7 @@
 extern crate proc_macro;
 
 mod macros;
+mod template;
 
 #[proc_macro_attribute]
 pub fn test_spec(
@@ -16,3 +17,26 @@ pub fn test_spec(
   
   proc_macro::TokenStream::from(output2)
 }
+
+/// Parses a template literal at compile time into a `mustache_core::Template`.
+#[proc_macro]
+pub fn mustache(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+  let output2 = match template::mustache(proc_macro2::TokenStream::from(input)) {
+    Ok(result) => result,
+    Err(error) => error.to_compile_error(),
+  };
+
+  proc_macro::TokenStream::from(output2)
+}
+
+/// Reads and parses a template file at compile time into a
+/// `mustache_core::Template`. The path is relative to the crate's manifest.
+#[proc_macro]
+pub fn include_mustache(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+  let output2 = match template::include_mustache(proc_macro2::TokenStream::from(input)) {
+    Ok(result) => result,
+    Err(error) => error.to_compile_error(),
+  };
+
+  proc_macro::TokenStream::from(output2)
+}
@@ -3,6 +3,7 @@ use proc_macro2::{Span, TokenStream};
 use quote::{format_ident, quote};
 use serde::Deserialize;
 use serde_yaml::Value;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use syn::parse::{Parse, ParseStream};
@@ -62,7 +63,7 @@ fn get_first_input_type(item: &syn::ItemFn) -> Result<&syn::Type> {
 
 fn new_test_basis(base_fn: &syn::ItemFn) -> Result<TestBasis<'_>> {
   let func_ident = &base_fn.sig.ident;
-  let input_type = get_first_input_type(&base_fn)?;
+  let input_type = get_first_input_type(base_fn)?;
 
   Ok(TestBasis {
     func_ident,
@@ -87,7 +88,7 @@ fn read_to_string(file_name: &str, span: &Span) -> Result<String> {
 }
 
 fn spec_suite_from_string(file_data: &str, span: &Span) -> Result<SpecSuite> {
-  serde_yaml::from_str::<SpecSuite>(&file_data).map_err(|err| Error::new(*span, err))
+  serde_yaml::from_str::<SpecSuite>(file_data).map_err(|err| Error::new(*span, err))
 }
 
 fn spec_case_from_value(value: Value, span: &Span) -> Result<SpecCase> {
@@ -121,8 +122,16 @@ fn new_test_mod_input(file_name: &syn::LitStr) -> Result<TestModInput> {
   let spec_suite = spec_suite_from_string(&file_data, &span)?;
 
   let mut test_fn_inputs: Vec<TestFnInput> = vec![];
+  let mut name_counts: HashMap<String, usize> = HashMap::new();
   for value in spec_suite.tests {
-    test_fn_inputs.push(new_test_fn_input(value, &span)?);
+    let mut test_fn_input = new_test_fn_input(value, &span)?;
+    // Spec suites may reuse a case name, but test functions need unique names.
+    let count = name_counts.entry(test_fn_input.test_name.to_string()).or_insert(0);
+    *count += 1;
+    if *count > 1 {
+      test_fn_input.test_name = format_ident!("{}_{}", test_fn_input.test_name, count);
+    }
+    test_fn_inputs.push(test_fn_input);
   }
 
   Ok(TestModInput {
@@ -135,7 +144,7 @@ fn new_test_mod_input(file_name: &syn::LitStr) -> Result<TestModInput> {
 fn new_test_spec(test_spec_args: &TestSpecArgs) -> Result<Vec<TestModInput>> {
   let mut test_mod_inputs: Vec<TestModInput> = vec![];
   for file_name in test_spec_args.file_names.iter() {
-    test_mod_inputs.push(new_test_mod_input(&file_name)?)
+    test_mod_inputs.push(new_test_mod_input(file_name)?)
   }
   Ok(test_mod_inputs)
 }
@@ -172,7 +181,7 @@ fn quote_test_mod(test_basis: &TestBasis, test_mod_input: &TestModInput) -> Toke
   
   let test_fns = test_fn_inputs
     .iter()
-    .map(|test_fn_input| quote_test_fn(&test_basis, &test_fn_input));
+    .map(|test_fn_input| quote_test_fn(test_basis, test_fn_input));
 
   quote! {
     #[doc = #mod_doc]
@@ -194,7 +203,7 @@ pub fn test_spec(attr: TokenStream, item: TokenStream) -> Result<TokenStream> {
 
   let test_mods = test_spec
     .iter()
-    .map(|spec| quote_test_mod(&test_basis, &spec));
+    .map(|spec| quote_test_mod(&test_basis, spec));
 
   Ok(quote! {
     #base_fn
@@ -0,0 +1,201 @@
+use mustache_core::{DelimiterTag, Position, Span, Tag, Template};
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use syn::{Error, Result};
+
+fn quote_key(key: &[&str]) -> TokenStream {
+  quote! { vec![#(#key),*] }
+}
+
+fn quote_position(position: &Position) -> TokenStream {
+  let Position { offset, line, column } = position;
+  quote! {
+    ::mustache_core::Position { offset: #offset, line: #line, column: #column }
+  }
+}
+
+fn quote_span(span: &Span) -> TokenStream {
+  let (start, end) = (quote_position(&span.start), quote_position(&span.end));
+  quote! { ::mustache_core::Span { start: #start, end: #end } }
+}
+
+fn quote_delimiters(delimiters: &DelimiterTag) -> TokenStream {
+  let (start, stop) = (delimiters.start, delimiters.stop);
+  let (span, standalone) = (quote_span(&delimiters.span), delimiters.standalone);
+  quote! {
+    ::mustache_core::DelimiterTag { start: #start, stop: #stop, span: #span, standalone: #standalone }
+  }
+}
+
+fn quote_tags(tags: &[Tag]) -> TokenStream {
+  let tags = tags.iter().map(quote_tag);
+  quote! { vec![#(#tags),*] }
+}
+
+fn quote_tag(tag: &Tag) -> TokenStream {
+  match tag {
+    Tag::Text(text) => {
+      let value = text.value;
+      let span = quote_span(&text.span);
+      quote! {
+        ::mustache_core::Tag::Text(::mustache_core::ValueTag { value: #value, span: #span, standalone: false })
+      }
+    }
+    Tag::Variable(variable) | Tag::Unescaped(variable) => {
+      let key = quote_key(&variable.key);
+      let span = quote_span(&variable.span);
+      let variant = match tag {
+        Tag::Variable(_) => quote! { Variable },
+        _ => quote! { Unescaped },
+      };
+      quote! {
+        ::mustache_core::Tag::#variant(::mustache_core::KeyTag { key: #key, span: #span })
+      }
+    }
+    Tag::Section(section) | Tag::Inverted(section) => {
+      let key = quote_key(&section.key);
+      let tags = quote_tags(&section.tags);
+      let source = section.source;
+      let delimiters = quote_delimiters(&section.delimiters);
+      let (span, close) = (quote_span(&section.span), quote_span(&section.close));
+      let (standalone, close_standalone) = (section.standalone, section.close_standalone);
+      let variant = match tag {
+        Tag::Section(_) => quote! { Section },
+        _ => quote! { Inverted },
+      };
+      quote! {
+        ::mustache_core::Tag::#variant(::mustache_core::ContextTag {
+          key: #key,
+          tags: #tags,
+          source: #source,
+          delimiters: #delimiters,
+          span: #span,
+          close: #close,
+          standalone: #standalone,
+          close_standalone: #close_standalone,
+        })
+      }
+    }
+    Tag::Comment(comment) => {
+      let (value, standalone) = (comment.value, comment.standalone);
+      let span = quote_span(&comment.span);
+      quote! {
+        ::mustache_core::Tag::Comment(::mustache_core::ValueTag {
+          value: #value,
+          span: #span,
+          standalone: #standalone,
+        })
+      }
+    }
+    Tag::Partial(partial) => {
+      let name = partial.name;
+      let key = match &partial.key {
+        Some(key) => {
+          let key = quote_key(key);
+          quote! { Some(#key) }
+        }
+        None => quote! { None },
+      };
+      let (indentation, standalone) = (partial.indentation, partial.standalone);
+      let span = quote_span(&partial.span);
+      quote! {
+        ::mustache_core::Tag::Partial(::mustache_core::PartialTag {
+          name: #name,
+          key: #key,
+          indentation: #indentation,
+          span: #span,
+          standalone: #standalone,
+        })
+      }
+    }
+    Tag::Parent(parent) => {
+      let (name, indentation) = (parent.name, parent.indentation);
+      let tags = quote_tags(&parent.tags);
+      let (span, close) = (quote_span(&parent.span), quote_span(&parent.close));
+      let (standalone, close_standalone) = (parent.standalone, parent.close_standalone);
+      quote! {
+        ::mustache_core::Tag::Parent(::mustache_core::ParentTag {
+          name: #name,
+          indentation: #indentation,
+          tags: #tags,
+          span: #span,
+          close: #close,
+          standalone: #standalone,
+          close_standalone: #close_standalone,
+        })
+      }
+    }
+    Tag::Block(block) => {
+      let name = block.name;
+      let tags = quote_tags(&block.tags);
+      let (span, close) = (quote_span(&block.span), quote_span(&block.close));
+      let (standalone, close_standalone) = (block.standalone, block.close_standalone);
+      quote! {
+        ::mustache_core::Tag::Block(::mustache_core::BlockTag {
+          name: #name,
+          tags: #tags,
+          span: #span,
+          close: #close,
+          standalone: #standalone,
+          close_standalone: #close_standalone,
+        })
+      }
+    }
+    Tag::Delimiters(delimiters) => {
+      let delimiters = quote_delimiters(delimiters);
+      quote! { ::mustache_core::Tag::Delimiters(#delimiters) }
+    }
+  }
+}
+
+fn quote_template(template: &Template) -> TokenStream {
+  let tags = quote_tags(&template.tags);
+  quote! { ::mustache_core::Template { tags: #tags, escaper: None } }
+}
+
+/// Expands `mustache!("...")` into the parsed template.
+pub fn mustache(input: TokenStream) -> Result<TokenStream> {
+  let text: syn::LitStr = syn::parse2(input)?;
+  let source = text.value();
+  let template = Template::compile(&source)
+    .map_err(|err| Error::new(text.span(), format!("invalid template: {}", err)))?;
+  Ok(quote_template(&template))
+}
+
+/// Expands `include_mustache!("path")` into the parsed template, reading the
+/// file relative to the including crate's manifest directory.
+pub fn include_mustache(input: TokenStream) -> Result<TokenStream> {
+  let file_name: syn::LitStr = syn::parse2(input)?;
+  let span = file_name.span();
+  let root = env::var("CARGO_MANIFEST_DIR").map_err(|err| Error::new(span, err))?;
+  let path: PathBuf = [root, file_name.value()].iter().collect();
+  let source = fs::read_to_string(&path)
+    .map_err(|err| Error::new(span, format!("{}: {}", path.display(), err)))?;
+  let template =
+    Template::compile(&source).map_err(|err| Error::new(span, format!("{}:{}", path.display(), err)))?;
+
+  let template = quote_template(&template);
+  let path = path.to_string_lossy();
+  // Including the file as well makes cargo rebuild when the template changes.
+  Ok(quote! {
+    {
+      const _: &str = include_str!(#path);
+      #template
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn syntax_errors_name_the_position() {
+    let error = mustache(quote! { "alpha\n{{#beta}}" }).unwrap_err();
+
+    assert_eq!("invalid template: 2:1: section 'beta' is never closed", error.to_string());
+  }
+}
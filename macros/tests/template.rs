@@ -0,0 +1,35 @@
+use macros::{include_mustache, mustache};
+use mustache_core::Result;
+use serde_json::json;
+
+#[test]
+fn inline_templates() -> Result<()> {
+  let template = mustache!("{{#people}}<{{name}}>{{/people}}{{^people}}nobody{{/people}}");
+
+  let some = template.render(&json!({ "people": [{ "name": "Ada" }, { "name": "Bo" }] }), |_: &str| None)?;
+  let none = template.render(&json!({ "people": [] }), |_: &str| None)?;
+
+  assert_eq!("<Ada><Bo>", some);
+  assert_eq!("nobody", none);
+  Ok(())
+}
+
+#[test]
+fn included_templates() -> Result<()> {
+  let template = include_mustache!("tests/templates/list.mustache");
+
+  let output = template.render(&json!({ "name": "Ada", "items": ["a", "b"] }), |_: &str| None)?;
+
+  assert_eq!("Hello Ada!\n  - a\n  - b\n", output);
+  Ok(())
+}
+
+#[test]
+fn partials_load_at_render_time() -> Result<()> {
+  let template = mustache!("[{{>inner}}]");
+
+  let output = template.render(&json!({ "x": 1 }), |_: &str| Some("{{x}}".to_string()))?;
+
+  assert_eq!("[1]", output);
+  Ok(())
+}
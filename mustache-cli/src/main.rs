@@ -0,0 +1,195 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use mustache_core::{
+  DirectoryLoader, Escapers, Formatter, Missing, Options, PartialLoader, Spacing, Template, Value,
+};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Renders a mustache template with JSON or YAML data.
+#[derive(Parser)]
+#[command(name = "mustache", version, args_conflicts_with_subcommands = true)]
+struct Cli {
+  #[command(subcommand)]
+  command: Option<Command>,
+  /// The template to render. Reads standard input when absent or `-`.
+  template: Option<PathBuf>,
+  /// The data to render with, as JSON, or YAML for `.yaml` and `.yml` files.
+  #[arg(short, long)]
+  data: Option<PathBuf>,
+  /// A directory holding partials, where `{{>name}}` loads `name.mustache`.
+  #[arg(short, long)]
+  partials: Option<PathBuf>,
+  /// Writes the output to this file rather than standard output.
+  #[arg(short, long)]
+  output: Option<PathBuf>,
+  /// Fails on keys and partials that resolve to nothing.
+  #[arg(long)]
+  strict: bool,
+  /// Escaping for `{{name}}` tags: html, none, json, shell, latex or csv.
+  #[arg(long, default_value = "html")]
+  escape: String,
+  /// Prints a JSON Schema for the data the template expects, rather than
+  /// rendering it.
+  #[arg(long)]
+  schema: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Formats templates in one style: tag spacing and the indentation of
+  /// standalone section tags.
+  Fmt {
+    /// The templates to format. Reads standard input when absent.
+    files: Vec<PathBuf>,
+    /// Rewrites the files in place rather than printing them.
+    #[arg(short, long)]
+    write: bool,
+    /// Space inside tags.
+    #[arg(long, value_enum, default_value = "tight")]
+    spacing: SpacingArg,
+    /// Spaces of indentation per enclosing section.
+    #[arg(long, default_value_t = 2)]
+    indent: usize,
+  },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SpacingArg {
+  /// `{{name}}`
+  Tight,
+  /// `{{ name }}`
+  Padded,
+}
+
+fn read_template(path: Option<&Path>) -> Result<String, String> {
+  match path {
+    Some(path) if path != Path::new("-") => {
+      fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+    _ => {
+      let mut text = String::new();
+      io::stdin()
+        .read_to_string(&mut text)
+        .map_err(|err| format!("standard input: {}", err))?;
+      Ok(text)
+    }
+  }
+}
+
+fn read_data(path: Option<&Path>) -> Result<Value, String> {
+  let path = match path {
+    Some(path) => path,
+    None => return Ok(Value::Object(Default::default())),
+  };
+  let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+  let is_yaml = matches!(
+    path.extension().and_then(|extension| extension.to_str()),
+    Some("yaml") | Some("yml")
+  );
+  if is_yaml {
+    serde_yaml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))
+  } else {
+    serde_json::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))
+  }
+}
+
+/// Partials from the directory given, if any.
+struct Partials(Option<DirectoryLoader>);
+
+impl PartialLoader for Partials {
+  fn load(&self, name: &str) -> io::Result<Option<String>> {
+    match &self.0 {
+      Some(loader) => loader.load(name),
+      None => Ok(None),
+    }
+  }
+}
+
+/// Writes the finished output, so a failed render leaves the output file as
+/// it was.
+fn write_output(path: Option<&Path>, output: &[u8]) -> Result<(), String> {
+  match path {
+    Some(path) => fs::write(path, output).map_err(|err| format!("{}: {}", path.display(), err)),
+    None => io::stdout().write_all(output).map_err(|err| err.to_string()),
+  }
+}
+
+fn run(cli: &Cli) -> Result<(), String> {
+  let source = read_template(cli.template.as_deref())?;
+  let data = read_data(cli.data.as_deref())?;
+  let origin = match &cli.template {
+    Some(path) if path != Path::new("-") => path.display().to_string(),
+    _ => "<stdin>".to_string(),
+  };
+  let template = Template::compile(&source).map_err(|err| format!("{}: {}", origin, err))?;
+  if cli.schema {
+    let schema = serde_json::to_string_pretty(&template.schema()).map_err(|err| err.to_string())?;
+    return write_output(cli.output.as_deref(), format!("{}\n", schema).as_bytes());
+  }
+
+  let escapers = Escapers::default();
+  let escaper = escapers
+    .get(&cli.escape)
+    .ok_or_else(|| format!("unknown escaping '{}'", cli.escape))?;
+  let missing = if cli.strict { Missing::Fail } else { Missing::Ignore };
+  let options = Options::new().escaper(escaper).missing(missing);
+  let partials = Partials(cli.partials.as_ref().map(DirectoryLoader::new));
+
+  // Partials that include each other stop at the render's depth limit.
+  let mut output = vec![];
+  template
+    .render_to_with(&mut output, &data, partials, &options)
+    .map_err(|err| format!("{}: {}", origin, err))?;
+  write_output(cli.output.as_deref(), &output)
+}
+
+fn fmt(files: &[PathBuf], write: bool, spacing: SpacingArg, indent: usize) -> Result<(), String> {
+  let spacing = match spacing {
+    SpacingArg::Tight => Spacing::Tight,
+    SpacingArg::Padded => Spacing::Padded,
+  };
+  let formatter = Formatter::new().spacing(spacing).indent(&" ".repeat(indent));
+  if files.is_empty() {
+    let source = read_template(None)?;
+    let formatted = formatter.format(&source).map_err(|err| format!("<stdin>: {}", err))?;
+    return io::stdout()
+      .write_all(formatted.as_bytes())
+      .map_err(|err| err.to_string());
+  }
+  for path in files {
+    let source = read_template(Some(path))?;
+    let formatted = formatter
+      .format(&source)
+      .map_err(|err| format!("{}: {}", path.display(), err))?;
+    if write {
+      fs::write(path, formatted).map_err(|err| format!("{}: {}", path.display(), err))?;
+    } else {
+      io::stdout()
+        .write_all(formatted.as_bytes())
+        .map_err(|err| err.to_string())?;
+    }
+  }
+  Ok(())
+}
+
+fn main() -> ExitCode {
+  let cli = Cli::parse();
+  let result = match &cli.command {
+    Some(Command::Fmt {
+      files,
+      write,
+      spacing,
+      indent,
+    }) => fmt(files, *write, *spacing, *indent),
+    None => run(&cli),
+  };
+  match result {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(message) => {
+      eprintln!("mustache: {}", message);
+      ExitCode::FAILURE
+    }
+  }
+}
@@ -0,0 +1,113 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn scratch(name: &str) -> PathBuf {
+  let directory = env::temp_dir().join(format!("mustache-cli-{}-{}", name, std::process::id()));
+  fs::create_dir_all(directory.join("partials")).unwrap();
+  directory
+}
+
+fn mustache(args: &[&str], stdin: &str) -> Output {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_mustache"))
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+  child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+  child.wait_with_output().unwrap()
+}
+
+#[test]
+fn renders_files_with_yaml_data_and_partials() {
+  let directory = scratch("files");
+  fs::write(directory.join("page.mustache"), "{{#items}}{{>item}}{{/items}}").unwrap();
+  fs::write(directory.join("partials/item.mustache"), "- {{.}}\n").unwrap();
+  fs::write(directory.join("data.yml"), "items: [a, b]\n").unwrap();
+  let path = |name: &str| directory.join(name).to_string_lossy().into_owned();
+
+  let output = mustache(
+    &[&path("page.mustache"), "-d", &path("data.yml"), "-p", &path("partials"), "-o", &path("out.txt")],
+    "",
+  );
+
+  assert!(output.status.success());
+  assert_eq!("- a\n- b\n", fs::read_to_string(directory.join("out.txt")).unwrap());
+  fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn renders_stdin_with_json_data() {
+  let directory = scratch("stdin");
+  let data = directory.join("data.json");
+  fs::write(&data, r#"{ "name": "<Ada>" }"#).unwrap();
+
+  let output = mustache(&["-d", &data.to_string_lossy(), "--escape", "none"], "Hi {{name}}");
+
+  assert!(output.status.success());
+  assert_eq!("Hi <Ada>", String::from_utf8_lossy(&output.stdout));
+  fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn reports_parse_errors() {
+  let output = mustache(&[], "alpha\n{{#beta}}");
+
+  assert!(!output.status.success());
+  assert_eq!(
+    "mustache: <stdin>: 2:1: section 'beta' is never closed\n",
+    String::from_utf8_lossy(&output.stderr)
+  );
+}
+
+#[test]
+fn strict_mode_reports_missing_keys() {
+  let output = mustache(&["--strict"], "{{name}}");
+
+  assert!(!output.status.success());
+  assert!(String::from_utf8_lossy(&output.stderr).contains("key 'name' not found"));
+}
+
+#[test]
+fn failed_renders_leave_the_output_alone() {
+  let directory = scratch("failed");
+  fs::write(directory.join("partials/loop.mustache"), "{{>loop}}").unwrap();
+  fs::write(directory.join("out.txt"), "before").unwrap();
+  let path = |name: &str| directory.join(name).to_string_lossy().into_owned();
+
+  let output = mustache(&["-p", &path("partials"), "-o", &path("out.txt")], "{{>loop}}");
+
+  assert!(!output.status.success());
+  assert!(String::from_utf8_lossy(&output.stderr).contains("partial 'loop'"));
+  assert_eq!("before", fs::read_to_string(directory.join("out.txt")).unwrap());
+  fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn prints_a_schema() {
+  let output = mustache(&["--schema"], "{{#user}}{{name}}{{/user}}");
+
+  assert!(output.status.success());
+  let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!("object", schema["properties"]["user"]["anyOf"][0]["type"]);
+}
+
+#[test]
+fn formats_files_in_place() {
+  let directory = scratch("fmt");
+  let path = directory.join("page.mustache");
+  fs::write(&path, "{{#items}}\n{{#tags}}\n- {{ name}}\n    {{/tags}}\n{{/items}}\n").unwrap();
+
+  let output = mustache(&["fmt", "--write", &path.to_string_lossy()], "");
+
+  assert!(output.status.success());
+  assert_eq!(
+    "{{#items}}\n  {{#tags}}\n- {{name}}\n  {{/tags}}\n{{/items}}\n",
+    fs::read_to_string(&path).unwrap()
+  );
+  fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,354 @@
+use mustache_core::{lookup, Context, Cst, CstNode, CstSection, CstTag, CstTagKind};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Converts between byte offsets and the line and UTF-16 column positions
+/// the protocol speaks in.
+pub struct LineIndex<'a> {
+  text: &'a str,
+  line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+  pub fn new(text: &'a str) -> LineIndex<'a> {
+    let mut line_starts = vec![0];
+    line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+    LineIndex { text, line_starts }
+  }
+
+  pub fn position(&self, offset: usize) -> lsp_types::Position {
+    let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+    let start = self.line_starts[line];
+    let character = self.text[start..offset].encode_utf16().count();
+    lsp_types::Position::new(line as u32, character as u32)
+  }
+
+  pub fn offset(&self, position: lsp_types::Position) -> usize {
+    let start = match self.line_starts.get(position.line as usize) {
+      Some(&start) => start,
+      None => return self.text.len(),
+    };
+    let line = &self.text[start..];
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+      if units >= position.character as usize || c == '\n' {
+        return start + i;
+      }
+      units += c.len_utf16();
+    }
+    self.text.len()
+  }
+
+  pub fn range(&self, range: Range<usize>) -> lsp_types::Range {
+    lsp_types::Range::new(self.position(range.start), self.position(range.end))
+  }
+}
+
+/// A parse error, placed from its position to the end of that line.
+pub struct Problem {
+  pub range: Range<usize>,
+  pub message: String,
+}
+
+pub fn problems(text: &str) -> Vec<Problem> {
+  let error = match Cst::parse(text) {
+    Ok(_) => return vec![],
+    Err(error) => error,
+  };
+  let start = error.position().map_or(0, |position| position.offset);
+  let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+  vec![Problem {
+    range: start..end,
+    message: error.message(),
+  }]
+}
+
+/// The tag under the cursor, and the sections around it.
+struct Found<'c, 'a> {
+  tag: &'c CstTag<'a>,
+  /// The section the tag opens or closes.
+  section: Option<&'c CstSection<'a>>,
+  scopes: Vec<&'c CstSection<'a>>,
+}
+
+fn contains(tag: &CstTag, offset: usize) -> bool {
+  tag.span.start.offset <= offset && offset < tag.span.end.offset
+}
+
+fn find<'c, 'a>(nodes: &'c [CstNode<'a>], offset: usize, scopes: &mut Vec<&'c CstSection<'a>>) -> Option<Found<'c, 'a>> {
+  for node in nodes {
+    match node {
+      CstNode::Text(_) => (),
+      CstNode::Tag(tag) if contains(tag, offset) => {
+        return Some(Found {
+          tag,
+          section: None,
+          scopes: scopes.clone(),
+        })
+      }
+      CstNode::Tag(_) => (),
+      CstNode::Section(section) => {
+        for tag in [&section.open, &section.close] {
+          if contains(tag, offset) {
+            return Some(Found {
+              tag,
+              section: Some(section),
+              scopes: scopes.clone(),
+            });
+          }
+        }
+        scopes.push(section);
+        if let Some(found) = find(&section.children, offset, scopes) {
+          return Some(found);
+        }
+        scopes.pop();
+      }
+    }
+  }
+  None
+}
+
+fn found_at<'c, 'a>(cst: &'c Cst<'a>, offset: usize) -> Option<Found<'c, 'a>> {
+  find(&cst.nodes, offset, &mut vec![])
+}
+
+/// The file a `{{>name}}` or `{{<name}}` under the cursor loads, looked for
+/// next to the template.
+pub fn definition(text: &str, offset: usize, template: &Path) -> Option<PathBuf> {
+  let cst = Cst::parse(text).ok()?;
+  let found = found_at(&cst, offset)?;
+  let name = found.tag.content.text;
+  if !matches!(found.tag.kind, CstTagKind::Partial | CstTagKind::Parent) || name.starts_with('*') {
+    return None;
+  }
+  let path = template.parent()?.join(format!("{}.mustache", name));
+  if path.is_file() {
+    Some(path)
+  } else {
+    None
+  }
+}
+
+/// The opening and closing tags of the section under the cursor.
+pub fn highlights(text: &str, offset: usize) -> Vec<Range<usize>> {
+  let cst = match Cst::parse(text) {
+    Ok(cst) => cst,
+    Err(_) => return vec![],
+  };
+  match found_at(&cst, offset).and_then(|found| found.section) {
+    Some(section) => vec![section.open.span.range(), section.close.span.range()],
+    None => vec![],
+  }
+}
+
+/// The keys of the sections around a tag, which change the context its key
+/// is looked up in. Inverted sections do not.
+fn scope_keys<'a>(scopes: &[&CstSection<'a>]) -> Vec<&'a str> {
+  scopes
+    .iter()
+    .filter(|section| section.open.kind == CstTagKind::Section)
+    .map(|section| section.open.content.text)
+    .collect()
+}
+
+/// The key path under the cursor, with the sections it is looked up in.
+pub fn hover(text: &str, offset: usize) -> Option<(String, Range<usize>)> {
+  let cst = Cst::parse(text).ok()?;
+  let found = found_at(&cst, offset)?;
+  let tag = found.tag;
+  let kind = match tag.kind {
+    CstTagKind::Variable => "variable",
+    CstTagKind::Triple | CstTagKind::Ampersand => "unescaped variable",
+    CstTagKind::Section => "section",
+    CstTagKind::Inverted => "inverted section",
+    CstTagKind::Close if found.section.map(|s| s.open.kind) == Some(CstTagKind::Section) => "section",
+    CstTagKind::Close if found.section.map(|s| s.open.kind) == Some(CstTagKind::Inverted) => "inverted section",
+    _ => return None,
+  };
+  let mut path = scope_keys(&found.scopes);
+  path.push(tag.content.text);
+  let path: Vec<String> = path.iter().map(|key| format!("`{}`", key)).collect();
+  Some((format!("{}: {}", kind, path.join(" › ")), tag.content.span.range()))
+}
+
+/// A key offered while typing a tag.
+#[derive(Debug, PartialEq)]
+pub struct Completion {
+  pub key: String,
+  /// What the key's value is in the sample data: an array, an object, text
+  /// or null.
+  pub detail: &'static str,
+}
+
+fn kind(value: &dyn Context) -> &'static str {
+  if value.items().is_some() {
+    "array"
+  } else if value.keys().is_some() {
+    "object"
+  } else if value.text().is_some() {
+    "text"
+  } else {
+    "null"
+  }
+}
+
+/// The value a section renders its body with: the first item of a list.
+fn item(value: &dyn Context) -> &dyn Context {
+  value.items().and_then(|mut items| items.next()).unwrap_or(value)
+}
+
+/// The sample data for a template: `page.json` next to `page.mustache`.
+pub fn sample_path(template: &Path) -> PathBuf {
+  template.with_extension("json")
+}
+
+/// Keys from the sample data that fit the tag being typed at the cursor,
+/// looked up within the sections around it.
+pub fn completions(text: &str, offset: usize, data: &dyn Context) -> Vec<Completion> {
+  let (start, stop) = Cst::delimiters_at(text, offset);
+  let before = &text[..offset];
+  let tag_start = match before.rfind(start) {
+    Some(tag_start) if !before[tag_start..].contains(stop) => tag_start,
+    _ => return vec![],
+  };
+  let inside = &before[tag_start + start.len()..];
+  let typed = inside.trim_start_matches(['#', '^', '&', '{', '/']).trim_start();
+  if typed.contains(char::is_whitespace) || inside.starts_with(['!', '>', '<', '$', '=']) {
+    return vec![];
+  }
+
+  // The tag being typed may not parse yet, so the sections are found with it
+  // left out.
+  let after = &text[offset..];
+  let line_end = after.find('\n').unwrap_or(after.len());
+  let tag_end = after[..line_end].find(stop).map_or(offset, |i| offset + i + stop.len());
+  let rest = format!("{}{}", &text[..tag_start], &text[tag_end..]);
+  let mut stack = vec![data];
+  if let Ok(cst) = Cst::parse(&rest) {
+    let mut scopes = vec![];
+    enclosing(&cst.nodes, tag_start, &mut scopes);
+    for key in scope_keys(&scopes) {
+      if let Some(value) = lookup(&stack, key) {
+        stack.push(item(value));
+      }
+    }
+  }
+
+  let (head, _) = typed.rsplit_once('.').unwrap_or(("", typed));
+  let objects: Vec<&dyn Context> = if head.is_empty() {
+    stack.iter().rev().copied().collect()
+  } else {
+    lookup(&stack, head).map(item).into_iter().collect()
+  };
+  let mut completions: Vec<Completion> = vec![];
+  for object in objects {
+    for key in object.keys().into_iter().flatten() {
+      if !completions.iter().any(|completion| completion.key == key) {
+        completions.push(Completion {
+          key: key.to_string(),
+          detail: object.get(key).map_or("null", kind),
+        });
+      }
+    }
+  }
+  completions
+}
+
+/// Collects the sections whose bodies hold the offset, outermost first.
+fn enclosing<'c, 'a>(nodes: &'c [CstNode<'a>], offset: usize, scopes: &mut Vec<&'c CstSection<'a>>) {
+  for node in nodes {
+    if let CstNode::Section(section) = node {
+      if section.open.span.end.offset <= offset && offset <= section.close.span.start.offset {
+        scopes.push(section);
+        enclosing(&section.children, offset, scopes);
+        return;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+  use std::env;
+  use std::fs;
+
+  #[test]
+  fn line_index_counts_utf16() {
+    let text = "a\n😀b\nc";
+    let index = LineIndex::new(text);
+
+    assert_eq!(lsp_types::Position::new(1, 2), index.position(6));
+    assert_eq!(6, index.offset(lsp_types::Position::new(1, 2)));
+    assert_eq!(text.len(), index.offset(lsp_types::Position::new(5, 0)));
+  }
+
+  #[test]
+  fn problems_are_placed() {
+    let problems = problems("alpha\n{{#beta}}\nx");
+
+    assert_eq!(1, problems.len());
+    assert_eq!(6..15, problems[0].range);
+    assert_eq!("section 'beta' is never closed", problems[0].message);
+  }
+
+  #[test]
+  fn sections_highlight_both_tags() {
+    let text = "{{#items}}{{name}}{{/items}}";
+
+    assert_eq!(vec![0..10, 18..28], highlights(text, 20));
+    assert_eq!(Vec::<Range<usize>>::new(), highlights(text, 12));
+  }
+
+  #[test]
+  fn hover_shows_the_key_path() {
+    let text = "{{#order}}{{^paid}}{{ customer.name }}{{/paid}}{{/order}}";
+
+    let (message, range) = hover(text, 24).unwrap();
+
+    assert_eq!("variable: `order` › `customer.name`", message);
+    assert_eq!("customer.name", &text[range]);
+  }
+
+  #[test]
+  fn partials_are_found_next_to_the_template() {
+    let directory = env::temp_dir().join(format!("mustache-lsp-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("row.mustache"), "{{name}}").unwrap();
+    let template = directory.join("page.mustache");
+
+    assert_eq!(Some(directory.join("row.mustache")), definition("{{> row}}", 4, &template));
+    assert_eq!(None, definition("{{>missing}}", 4, &template));
+    fs::remove_dir_all(directory).unwrap();
+  }
+
+  #[test]
+  fn completions_follow_sections() {
+    let data = json!({ "title": "T", "items": [{ "name": "a", "price": { "amount": 1 } }] });
+    let keys = |text: &str, offset: usize| -> Vec<String> {
+      completions(text, offset, &data).into_iter().map(|c| c.key).collect()
+    };
+
+    assert_eq!(vec!["items", "title"], keys("{{ti", 4));
+    let details: Vec<&str> = completions("{{ti", 4, &data).into_iter().map(|c| c.detail).collect();
+    assert_eq!(vec!["array", "text"], details);
+    assert_eq!(vec!["name", "price", "items", "title"], keys("{{#items}}{{n}}{{/items}}", 12));
+    assert_eq!(vec!["amount"], keys("{{#items}}{{price.a\n{{/items}}", 19));
+    assert!(keys("{{>ro", 5).is_empty());
+    assert!(keys("{{a}} b", 7).is_empty());
+  }
+
+  #[test]
+  fn completions_use_the_delimiters_in_effect() {
+    let data = json!({ "title": "T", "items": [{ "name": "a" }] });
+    let keys = |text: &str, offset: usize| -> Vec<String> {
+      completions(text, offset, &data).into_iter().map(|c| c.key).collect()
+    };
+
+    assert_eq!(vec!["items", "title"], keys("{{=<% %>=}}<%ti", 15));
+    assert_eq!(vec!["name", "items", "title"], keys("{{=<% %>=}}<%#items%><%n%><%/items%>", 23));
+    assert!(keys("{{=<% %>=}}{{ti", 15).is_empty());
+    assert!(keys("{{=<% %>=}}<%a%> b", 18).is_empty());
+  }
+}
@@ -0,0 +1,222 @@
+mod analysis;
+
+use analysis::LineIndex;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+  DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, DocumentHighlightRequest, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+  CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse, Diagnostic,
+  DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+  DocumentHighlight, DocumentHighlightKind, DocumentHighlightParams, GotoDefinitionParams,
+  GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent,
+  MarkupKind, OneOf, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentPositionParams,
+  TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+struct Server {
+  connection: Connection,
+  /// The text of each open template.
+  documents: HashMap<Url, String>,
+}
+
+fn capabilities() -> ServerCapabilities {
+  ServerCapabilities {
+    text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+    hover_provider: Some(HoverProviderCapability::Simple(true)),
+    definition_provider: Some(OneOf::Left(true)),
+    document_highlight_provider: Some(OneOf::Left(true)),
+    completion_provider: Some(CompletionOptions {
+      trigger_characters: Some(vec!["{".into(), ".".into(), "#".into(), "^".into(), "&".into()]),
+      ..CompletionOptions::default()
+    }),
+    ..ServerCapabilities::default()
+  }
+}
+
+impl Server {
+  fn send(&self, message: Message) -> Result<()> {
+    self.connection.sender.send(message)?;
+    Ok(())
+  }
+
+  fn publish(&self, uri: &Url) -> Result<()> {
+    let text = self.documents.get(uri).map_or("", String::as_str);
+    let index = LineIndex::new(text);
+    let diagnostics = analysis::problems(text)
+      .into_iter()
+      .map(|problem| Diagnostic {
+        range: index.range(problem.range),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("mustache".into()),
+        message: problem.message,
+        ..Diagnostic::default()
+      })
+      .collect();
+    let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics, None);
+    self.send(Message::Notification(Notification::new(PublishDiagnostics::METHOD.into(), params)))
+  }
+
+  fn open(&mut self, params: DidOpenTextDocumentParams) -> Result<()> {
+    let uri = params.text_document.uri;
+    self.documents.insert(uri.clone(), params.text_document.text);
+    self.publish(&uri)
+  }
+
+  fn change(&mut self, params: DidChangeTextDocumentParams) -> Result<()> {
+    let uri = params.text_document.uri;
+    if let Some(change) = params.content_changes.into_iter().last() {
+      self.documents.insert(uri.clone(), change.text);
+    }
+    self.publish(&uri)
+  }
+
+  /// Forgets the document and clears its diagnostics, which the client would
+  /// otherwise keep showing.
+  fn close(&mut self, params: DidCloseTextDocumentParams) -> Result<()> {
+    let uri = params.text_document.uri;
+    self.documents.remove(&uri);
+    let params = PublishDiagnosticsParams::new(uri, vec![], None);
+    self.send(Message::Notification(Notification::new(PublishDiagnostics::METHOD.into(), params)))
+  }
+
+  /// Handles a notification. One whose parameters do not parse is logged and
+  /// skipped, as there is no one to answer.
+  fn notify(&mut self, notification: Notification) -> Result<()> {
+    let Notification { method, params } = notification;
+    let handled = match method.as_str() {
+      DidOpenTextDocument::METHOD => serde_json::from_value(params).map(|params| self.open(params)),
+      DidChangeTextDocument::METHOD => serde_json::from_value(params).map(|params| self.change(params)),
+      DidCloseTextDocument::METHOD => serde_json::from_value(params).map(|params| self.close(params)),
+      _ => return Ok(()),
+    };
+    handled.unwrap_or_else(|err| {
+      eprintln!("mustache-lsp: skipping '{}' with malformed parameters: {}", method, err);
+      Ok(())
+    })
+  }
+
+  /// The open text at a request's position, and the byte offset there.
+  fn locate(&self, params: &TextDocumentPositionParams) -> Option<(&str, usize)> {
+    let text = self.documents.get(&params.text_document.uri)?;
+    Some((text, LineIndex::new(text).offset(params.position)))
+  }
+
+  fn hover(&self, params: HoverParams) -> Option<Hover> {
+    let (text, offset) = self.locate(&params.text_document_position_params)?;
+    let (message, range) = analysis::hover(text, offset)?;
+    Some(Hover {
+      contents: HoverContents::Markup(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: message,
+      }),
+      range: Some(LineIndex::new(text).range(range)),
+    })
+  }
+
+  fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+    let position = &params.text_document_position_params;
+    let (text, offset) = self.locate(position)?;
+    let template = position.text_document.uri.to_file_path().ok()?;
+    let path = analysis::definition(text, offset, &template)?;
+    let uri = Url::from_file_path(path).ok()?;
+    Some(GotoDefinitionResponse::Scalar(Location::new(uri, Range::default())))
+  }
+
+  fn highlights(&self, params: DocumentHighlightParams) -> Option<Vec<DocumentHighlight>> {
+    let (text, offset) = self.locate(&params.text_document_position_params)?;
+    let index = LineIndex::new(text);
+    let highlights = analysis::highlights(text, offset)
+      .into_iter()
+      .map(|range| DocumentHighlight {
+        range: index.range(range),
+        kind: Some(DocumentHighlightKind::TEXT),
+      })
+      .collect();
+    Some(highlights)
+  }
+
+  fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+    let position = &params.text_document_position;
+    let (text, offset) = self.locate(position)?;
+    let template = position.text_document.uri.to_file_path().ok()?;
+    let sample = fs::read_to_string(analysis::sample_path(&template)).ok()?;
+    let data: Value = serde_json::from_str(&sample).ok()?;
+    let items = analysis::completions(text, offset, &data)
+      .into_iter()
+      .map(|completion| CompletionItem {
+        label: completion.key,
+        kind: Some(CompletionItemKind::FIELD),
+        detail: Some(completion.detail.into()),
+        ..CompletionItem::default()
+      })
+      .collect();
+    Some(CompletionResponse::Array(items))
+  }
+
+  /// Answers a request, with `InvalidParams` if its parameters do not parse.
+  fn request(&self, request: Request) -> Result<()> {
+    let Request { id, method, params } = request;
+    let result = match method.as_str() {
+      HoverRequest::METHOD => {
+        serde_json::from_value(params).map(|params| serde_json::to_value(self.hover(params)))
+      }
+      GotoDefinition::METHOD => {
+        serde_json::from_value(params).map(|params| serde_json::to_value(self.definition(params)))
+      }
+      DocumentHighlightRequest::METHOD => {
+        serde_json::from_value(params).map(|params| serde_json::to_value(self.highlights(params)))
+      }
+      Completion::METHOD => {
+        serde_json::from_value(params).map(|params| serde_json::to_value(self.completion(params)))
+      }
+      method => return self.refuse(id, method),
+    };
+    let response = match result {
+      Ok(result) => Response::new_ok(id, result?),
+      Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+    };
+    self.send(Message::Response(response))
+  }
+
+  fn refuse(&self, id: RequestId, method: &str) -> Result<()> {
+    let message = format!("unsupported request '{}'", method);
+    self.send(Message::Response(Response::new_err(id, ErrorCode::MethodNotFound as i32, message)))
+  }
+
+  fn run(&mut self) -> Result<()> {
+    while let Ok(message) = self.connection.receiver.recv() {
+      match message {
+        Message::Request(request) => {
+          if self.connection.handle_shutdown(&request)? {
+            return Ok(());
+          }
+          self.request(request)?;
+        }
+        Message::Notification(notification) => self.notify(notification)?,
+        Message::Response(_) => (),
+      }
+    }
+    Ok(())
+  }
+}
+
+fn main() -> Result<()> {
+  let (connection, io_threads) = Connection::stdio();
+  connection.initialize(serde_json::to_value(capabilities())?)?;
+  let mut server = Server {
+    connection,
+    documents: HashMap::new(),
+  };
+  server.run()?;
+  drop(server);
+  io_threads.join()?;
+  Ok(())
+}
@@ -0,0 +1,107 @@
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+
+fn send(stdin: &mut ChildStdin, message: Value) {
+  let body = message.to_string();
+  write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+  stdin.flush().unwrap();
+}
+
+fn receive(stdout: &mut BufReader<ChildStdout>) -> Value {
+  let mut length = 0;
+  loop {
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+    if let Some(value) = line.strip_prefix("Content-Length: ") {
+      length = value.parse().unwrap();
+    }
+  }
+  let mut body = vec![0; length];
+  stdout.read_exact(&mut body).unwrap();
+  serde_json::from_slice(&body).unwrap()
+}
+
+#[test]
+fn diagnostics_and_hover_over_stdio() {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_mustache-lsp"))
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()
+    .unwrap();
+  let mut stdin = child.stdin.take().unwrap();
+  let mut stdout = BufReader::new(child.stdout.take().unwrap());
+  let uri = "file:///templates/page.mustache";
+
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "capabilities": {} } }));
+  let initialized = receive(&mut stdout);
+  assert_eq!(json!(true), initialized["result"]["capabilities"]["hoverProvider"]);
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }));
+
+  let document = json!({ "uri": uri, "languageId": "mustache", "version": 1, "text": "hi\n{{#user}}" });
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": { "textDocument": document } }));
+  let published = receive(&mut stdout);
+  assert_eq!("textDocument/publishDiagnostics", published["method"]);
+  let diagnostic = &published["params"]["diagnostics"][0];
+  assert_eq!("section 'user' is never closed", diagnostic["message"]);
+  assert_eq!(json!({ "line": 1, "character": 0 }), diagnostic["range"]["start"]);
+
+  let change = json!({ "textDocument": { "uri": uri, "version": 2 }, "contentChanges": [{ "text": "{{#user}}{{name}}{{/user}}" }] });
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "textDocument/didChange", "params": change }));
+  let published = receive(&mut stdout);
+  assert_eq!(json!([]), published["params"]["diagnostics"]);
+
+  let position = json!({ "textDocument": { "uri": uri }, "position": { "line": 0, "character": 12 } });
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": position }));
+  let hover = receive(&mut stdout);
+  assert_eq!("variable: `user` › `name`", hover["result"]["contents"]["value"]);
+
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": { "textDocument": document } }));
+  assert_eq!(1, receive(&mut stdout)["params"]["diagnostics"].as_array().unwrap().len());
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "textDocument/didClose", "params": { "textDocument": { "uri": uri } } }));
+  let published = receive(&mut stdout);
+  assert_eq!(json!(uri), published["params"]["uri"]);
+  assert_eq!(json!([]), published["params"]["diagnostics"]);
+
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }));
+  assert_eq!(json!(3), receive(&mut stdout)["id"]);
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "exit" }));
+  assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn malformed_parameters_do_not_stop_the_server() {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_mustache-lsp"))
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .unwrap();
+  let mut stdin = child.stdin.take().unwrap();
+  let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "capabilities": {} } }));
+  receive(&mut stdout);
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }));
+
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": { "uri": 7 } }));
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": { "position": "top" } }));
+  let refused = receive(&mut stdout);
+  assert_eq!(json!(2), refused["id"]);
+  assert_eq!(json!(-32602), refused["error"]["code"]);
+
+  let position = json!({ "textDocument": { "uri": "file:///closed.mustache" }, "position": { "line": 0, "character": 0 } });
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "id": 3, "method": "textDocument/hover", "params": position }));
+  let answered = receive(&mut stdout);
+  assert_eq!(json!(3), answered["id"]);
+  assert_eq!(Value::Null, answered["result"]);
+
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "id": 4, "method": "shutdown" }));
+  assert_eq!(json!(4), receive(&mut stdout)["id"]);
+  send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "exit" }));
+  assert!(child.wait().unwrap().success());
+}
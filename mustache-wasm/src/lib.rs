@@ -1,7 +1,8 @@
 mod utils;
 
 use js_sys::Function;
-use mustache_core::Value;
+use std::ops::Range;
+use mustache_core::{IncrementalParse, OwnedTemplate, Template, Value};
 use wasm_bindgen::prelude::*;
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -22,11 +23,145 @@ fn load_partial(partials: &Function, key: &str) -> Option<String> {
 }
 
 #[wasm_bindgen]
+#[allow(deprecated)]
 pub fn render(text: &str, data: &JsValue, partials: &Function) -> Result<String, JsValue> {
+    utils::set_panic_hook();
     let context: Value = data
         .into_serde()
         .map_err(|err| JsValue::from(err.to_string()))?;
 
-    mustache_core::render(text, &context, |key| load_partial(partials, key))
-        .map_err(|err| JsValue::from(&err))
+    mustache_core::render(text, &context, |key: &str| load_partial(partials, key))
+        .map_err(|err| JsValue::from(err.to_string()))
+}
+
+/// Parses a template ahead of time into the compact binary form that
+/// `render_compiled` takes.
+#[wasm_bindgen]
+pub fn compile(text: &str) -> Result<Vec<u8>, JsValue> {
+    utils::set_panic_hook();
+    Template::compile(text)
+        .and_then(|template| template.to_owned_template().to_bytes())
+        .map_err(|err| JsValue::from(err.to_string()))
+}
+
+/// Generates a standalone JavaScript module that renders the template, so a
+/// bundle needs neither this package nor the template source at runtime.
+#[wasm_bindgen]
+pub fn javascript(text: &str) -> Result<String, JsValue> {
+    utils::set_panic_hook();
+    Template::compile(text)
+        .map(|template| template.javascript())
+        .map_err(|err| JsValue::from(err.to_string()))
+}
+
+#[wasm_bindgen]
+#[allow(deprecated)]
+pub fn render_compiled(bytes: &[u8], data: &JsValue, partials: &Function) -> Result<String, JsValue> {
+    utils::set_panic_hook();
+    let template = OwnedTemplate::from_bytes(bytes).map_err(|err| JsValue::from(err.to_string()))?;
+    let context: Value = data
+        .into_serde()
+        .map_err(|err| JsValue::from(err.to_string()))?;
+
+    template
+        .template()
+        .render(&context, |key: &str| load_partial(partials, key))
+        .map_err(|err| JsValue::from(err.to_string()))
+}
+
+/// A template kept parsed while it is edited, so an editor can re-render on
+/// every keystroke without tokenizing the whole source again.
+#[wasm_bindgen]
+pub struct Document {
+    parse: IncrementalParse,
+}
+
+/// The byte offset of a UTF-16 index into `text`, as JavaScript counts, or
+/// `None` if the index lies inside a character or past the end.
+fn byte_offset(text: &str, index: usize) -> Option<usize> {
+    let mut units = 0;
+    for (offset, c) in text.char_indices() {
+        if units >= index {
+            return (units == index).then_some(offset);
+        }
+        units += c.len_utf16();
+    }
+    (units == index).then_some(text.len())
+}
+
+/// The byte range between two UTF-16 indices into `text`.
+fn byte_range(text: &str, start: usize, end: usize) -> Result<Range<usize>, String> {
+    let offset = |index| {
+        byte_offset(text, index)
+            .ok_or_else(|| format!("index {} is inside a character or past the end", index))
+    };
+    let (start, end) = (offset(start)?, offset(end)?);
+    if start > end {
+        return Err("the edit ends before it starts".to_string());
+    }
+    Ok(start..end)
+}
+
+#[wasm_bindgen]
+impl Document {
+    #[wasm_bindgen(constructor)]
+    pub fn new(text: &str) -> Document {
+        utils::set_panic_hook();
+        Document {
+            parse: IncrementalParse::new(text),
+        }
+    }
+
+    /// Replaces the text between two string indices. Fails if either index
+    /// splits a surrogate pair, leaving the text as it was.
+    pub fn edit(&mut self, start: usize, end: usize, replacement: &str) -> Result<(), JsValue> {
+        let range = byte_range(self.parse.text(), start, end).map_err(JsValue::from)?;
+        self.parse.edit(range, replacement);
+        Ok(())
+    }
+
+    pub fn text(&self) -> String {
+        self.parse.text().to_string()
+    }
+
+    #[allow(deprecated)]
+    pub fn render(&self, data: &JsValue, partials: &Function) -> Result<String, JsValue> {
+        let context: Value = data
+            .into_serde()
+            .map_err(|err| JsValue::from(err.to_string()))?;
+
+        self.parse
+            .template()
+            .and_then(|template| template.render(&context, |key: &str| load_partial(partials, key)))
+            .map_err(|err| JsValue::from(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_count_utf16_units() {
+        let text = "a\u{e9}\u{1f600}b";
+        assert_eq!(Ok(0..1), byte_range(text, 0, 1));
+        assert_eq!(Ok(3..7), byte_range(text, 2, 4));
+        assert_eq!(Ok(8..8), byte_range(text, 5, 5));
+        assert!(byte_range(text, 3, 4).is_err());
+        assert!(byte_range(text, 6, 6).is_err());
+        assert!(byte_range(text, 2, 1).is_err());
+    }
+
+    #[test]
+    fn replacing_an_emoji() {
+        // 😀 and 😁 share their high surrogate, so a diff of UTF-16 units
+        // finds only the low one changed.
+        let text = "{{! \u{1f600} }}";
+        assert!(byte_range(text, 5, 6).is_err());
+
+        let mut parse = IncrementalParse::new(text);
+        parse.edit(byte_range(text, 4, 6).unwrap(), "\u{1f601}");
+        assert_eq!("{{! \u{1f601} }}", parse.text());
+        assert!(parse.template().is_ok());
+    }
 }
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+
+use super::loader::PartialLoader;
+use super::parser;
+use super::types::{Error, Position, Result, Tag, Template};
+
+/// A partial or parent that tags include: its name, where its tag is, and the
+/// blocks overridden where it renders.
+type Include<'a> = (&'a str, Position, Vec<String>);
+
+/// The partials and parents that tags always include. Anything in a section
+/// or inverted section might never render, and neither might an overridden
+/// block's default.
+fn included<'a>(tags: &[Tag<'a>], overridden: &[String], out: &mut Vec<Include<'a>>) {
+  for tag in tags {
+    match tag {
+      Tag::Partial(partial) if partial.key.is_none() => {
+        out.push((partial.name, partial.span.start, overridden.to_vec()))
+      }
+      Tag::Parent(parent) => {
+        let mut overrides = overridden.to_vec();
+        for child in parent.tags.iter() {
+          if let Tag::Block(block) = child {
+            overrides.push(block.name.to_string());
+          }
+        }
+        out.push((parent.name, parent.span.start, overrides));
+      }
+      Tag::Block(block) if !overridden.iter().any(|name| name == block.name) => {
+        included(&block.tags, overridden, out)
+      }
+      _ => (),
+    }
+  }
+}
+
+struct Checker<P: PartialLoader> {
+  partials: P,
+  /// The partials being checked, outermost first.
+  path: Vec<String>,
+  /// Partials found to be free of cycles with no blocks overridden.
+  done: HashSet<String>,
+}
+
+impl<P: PartialLoader> Checker<P> {
+  fn check_tags(&mut self, tags: &[Tag], overridden: &[String]) -> Result<()> {
+    let mut found = vec![];
+    included(tags, overridden, &mut found);
+    for (name, position, overrides) in found {
+      self.check(name, position, &overrides)?;
+    }
+    Ok(())
+  }
+
+  /// Checks the partial included by the tag at `position`.
+  fn check(&mut self, name: &str, position: Position, overridden: &[String]) -> Result<()> {
+    if let Some(start) = self.path.iter().position(|open| open == name) {
+      let mut names = self.path[start..].to_vec();
+      names.push(name.to_string());
+      return Err(Error::PartialCycle { position, names });
+    }
+    if self.done.contains(name) {
+      return Ok(());
+    }
+
+    let source = self.partials.load(name).map_err(|error| Error::Load {
+      position,
+      name: name.to_string(),
+      error,
+    })?;
+    if let Some(source) = source {
+      let (template, _) = parser::template(&source).map_err(|error| Error::Partial {
+        position,
+        name: name.to_string(),
+        error: Box::new(error),
+      })?;
+      self.path.push(name.to_string());
+      let result = self.check_tags(&template.tags, overridden);
+      self.path.pop();
+      // A cycle is reported at the tag in the template being checked that
+      // leads into it.
+      result.map_err(|error| match error {
+        Error::PartialCycle { names, .. } => Error::PartialCycle { position, names },
+        _ => error,
+      })?;
+    }
+    if overridden.is_empty() {
+      self.done.insert(name.to_string());
+    }
+    Ok(())
+  }
+}
+
+impl<'a> Template<'a> {
+  /// Loads the partials this template includes and fails with
+  /// `Error::PartialCycle` if some always include each other, blocks'
+  /// default content included unless a parent overrides it. Recursion through
+  /// a section can end with the data, so it is allowed; the render's depth
+  /// limit still stops it if the data never does.
+  pub fn check_partials<P>(&self, partials: P) -> Result<()>
+  where
+    P: PartialLoader,
+  {
+    let mut checker = Checker {
+      partials,
+      path: vec![],
+      done: HashSet::new(),
+    };
+    checker.check_tags(&self.tags, &[])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::HashMapLoader;
+
+  #[test]
+  fn cycles_outside_sections() -> Result<()> {
+    let partials = HashMapLoader::new()
+      .partial("a", "a{{>b}}")
+      .partial("b", "b{{<c}}{{/c}}")
+      .partial("c", "{{#x}}{{>c}}{{/x}}{{>a}}");
+    let template = Template::compile("{{>a}}")?;
+
+    match template.check_partials(partials) {
+      Err(Error::PartialCycle { position, names }) => {
+        assert_eq!(vec!["a", "b", "c", "a"], names);
+        assert_eq!(0, position.offset);
+      }
+      other => panic!("expected a cycle, got {:?}", other),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn recursion_through_sections() -> Result<()> {
+    let partials = HashMapLoader::new()
+      .partial("node", "{{name}}{{#children}}{{>node}}{{/children}}{{^leaf}}{{>node}}{{/leaf}}");
+    let template = Template::compile("{{>node}}{{>missing}}{{>*dynamic}}")?;
+
+    template.check_partials(partials)
+  }
+
+  #[test]
+  fn cycles_through_blocks_unless_overridden() -> Result<()> {
+    let partials = HashMapLoader::new().partial("page", "{{$body}}{{>page}}{{/body}}");
+
+    match Template::compile("{{>page}}")?.check_partials(partials.clone()) {
+      Err(Error::PartialCycle { names, .. }) => assert_eq!(vec!["page", "page"], names),
+      other => panic!("expected a cycle, got {:?}", other),
+    }
+    Template::compile("{{<page}}{{$body}}end{{/body}}{{/page}}")?.check_partials(partials)
+  }
+}
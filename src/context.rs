@@ -0,0 +1,287 @@
+use serde::Serialize;
+use std::borrow::Cow;
+
+use super::lambda::Lambda;
+use super::types::{Error, Result, Value};
+
+/// Data a template renders from. The resolver walks keys through `get`, and
+/// the emitter asks values for their truthiness, items and text, or calls
+/// them when they are lambdas.
+pub trait Context {
+  /// The value under `name`, if this value has one.
+  fn get(&self, name: &str) -> Option<&dyn Context>;
+
+  /// Whether a section over this value renders, and an inverted section not.
+  fn is_truthy(&self) -> bool;
+
+  /// The items of a list, each rendered once by a section over it.
+  fn items(&self) -> Option<Box<dyn Iterator<Item = &dyn Context> + '_>> {
+    None
+  }
+
+  /// The names `get` finds in a map, for tools such as editors that offer
+  /// keys to choose from.
+  fn keys(&self) -> Option<Box<dyn Iterator<Item = &str> + '_>> {
+    None
+  }
+
+  /// The text this value interpolates as, if it has one.
+  fn text(&self) -> Option<Cow<'_, str>>;
+
+  /// The host function to call in place of this value, if it is one.
+  fn lambda(&self) -> Option<&Lambda> {
+    None
+  }
+}
+
+impl<T: Context + ?Sized> Context for &T {
+  fn get(&self, name: &str) -> Option<&dyn Context> {
+    (**self).get(name)
+  }
+
+  fn is_truthy(&self) -> bool {
+    (**self).is_truthy()
+  }
+
+  fn items(&self) -> Option<Box<dyn Iterator<Item = &dyn Context> + '_>> {
+    (**self).items()
+  }
+
+  fn keys(&self) -> Option<Box<dyn Iterator<Item = &str> + '_>> {
+    (**self).keys()
+  }
+
+  fn text(&self) -> Option<Cow<'_, str>> {
+    (**self).text()
+  }
+
+  fn lambda(&self) -> Option<&Lambda> {
+    (**self).lambda()
+  }
+}
+
+fn bool_to_str(flag: bool) -> &'static str {
+  if flag {
+    "true"
+  } else {
+    "false"
+  }
+}
+
+impl Context for Value {
+  fn get(&self, name: &str) -> Option<&dyn Context> {
+    Value::get(self, name).map(|value| value as &dyn Context)
+  }
+
+  fn is_truthy(&self) -> bool {
+    match self {
+      Value::Null => false,
+      Value::Bool(flag) => *flag,
+      Value::Array(vec) => !vec.is_empty(),
+      _ => true,
+    }
+  }
+
+  fn items(&self) -> Option<Box<dyn Iterator<Item = &dyn Context> + '_>> {
+    match self {
+      Value::Array(vec) => Some(Box::new(vec.iter().map(|value| value as &dyn Context))),
+      _ => None,
+    }
+  }
+
+  fn keys(&self) -> Option<Box<dyn Iterator<Item = &str> + '_>> {
+    match self {
+      Value::Object(map) => Some(Box::new(map.keys().map(String::as_str))),
+      _ => None,
+    }
+  }
+
+  fn text(&self) -> Option<Cow<'_, str>> {
+    match self {
+      Value::String(string) => Some(Cow::Borrowed(string)),
+      Value::Number(number) => Some(Cow::Owned(number.to_string())),
+      Value::Bool(flag) => Some(Cow::Borrowed(bool_to_str(*flag))),
+      _ => None,
+    }
+  }
+}
+
+/// `Serialize` data converted into a context, for data that isn't a
+/// `serde_json::Value` already. `new` copies the data with
+/// `serde_json::to_value`, so it costs as much as serializing and fails where
+/// that would; the result can be rendered any number of times.
+///
+/// There is no context that reads borrowed `Serialize` data in place: `get`
+/// lends out values inside the context, and serde only shows a field to the
+/// serializer for the length of one call.
+pub struct Converted(Value);
+
+impl Converted {
+  pub fn new<T>(data: &T) -> Result<Converted>
+  where
+    T: Serialize + ?Sized,
+  {
+    serde_json::to_value(data).map(Converted).map_err(Error::Serialize)
+  }
+}
+
+impl Context for Converted {
+  fn get(&self, name: &str) -> Option<&dyn Context> {
+    Context::get(&self.0, name)
+  }
+
+  fn is_truthy(&self) -> bool {
+    self.0.is_truthy()
+  }
+
+  fn items(&self) -> Option<Box<dyn Iterator<Item = &dyn Context> + '_>> {
+    self.0.items()
+  }
+
+  fn keys(&self) -> Option<Box<dyn Iterator<Item = &str> + '_>> {
+    self.0.keys()
+  }
+
+  fn text(&self) -> Option<Cow<'_, str>> {
+    self.0.text()
+  }
+
+  fn lambda(&self) -> Option<&Lambda> {
+    self.0.lambda()
+  }
+}
+
+#[cfg(feature = "yaml")]
+impl Context for serde_yaml::Value {
+  fn get(&self, name: &str) -> Option<&dyn Context> {
+    serde_yaml::Value::get(self, name).map(|value| value as &dyn Context)
+  }
+
+  fn is_truthy(&self) -> bool {
+    match self {
+      serde_yaml::Value::Null => false,
+      serde_yaml::Value::Bool(flag) => *flag,
+      serde_yaml::Value::Sequence(vec) => !vec.is_empty(),
+      _ => true,
+    }
+  }
+
+  fn items(&self) -> Option<Box<dyn Iterator<Item = &dyn Context> + '_>> {
+    match self {
+      serde_yaml::Value::Sequence(vec) => Some(Box::new(vec.iter().map(|value| value as &dyn Context))),
+      _ => None,
+    }
+  }
+
+  fn keys(&self) -> Option<Box<dyn Iterator<Item = &str> + '_>> {
+    match self {
+      serde_yaml::Value::Mapping(map) => Some(Box::new(map.iter().filter_map(|(key, _)| key.as_str()))),
+      _ => None,
+    }
+  }
+
+  fn text(&self) -> Option<Cow<'_, str>> {
+    match self {
+      serde_yaml::Value::String(string) => Some(Cow::Borrowed(string)),
+      serde_yaml::Value::Number(number) => Some(Cow::Owned(number.to_string())),
+      serde_yaml::Value::Bool(flag) => Some(Cow::Borrowed(bool_to_str(*flag))),
+      _ => None,
+    }
+  }
+}
+
+#[cfg(feature = "toml")]
+impl Context for toml::Value {
+  fn get(&self, name: &str) -> Option<&dyn Context> {
+    toml::Value::get(self, name).map(|value| value as &dyn Context)
+  }
+
+  fn is_truthy(&self) -> bool {
+    match self {
+      toml::Value::Boolean(flag) => *flag,
+      toml::Value::Array(vec) => !vec.is_empty(),
+      _ => true,
+    }
+  }
+
+  fn items(&self) -> Option<Box<dyn Iterator<Item = &dyn Context> + '_>> {
+    match self {
+      toml::Value::Array(vec) => Some(Box::new(vec.iter().map(|value| value as &dyn Context))),
+      _ => None,
+    }
+  }
+
+  fn keys(&self) -> Option<Box<dyn Iterator<Item = &str> + '_>> {
+    match self {
+      toml::Value::Table(table) => Some(Box::new(table.keys().map(String::as_str))),
+      _ => None,
+    }
+  }
+
+  fn text(&self) -> Option<Cow<'_, str>> {
+    match self {
+      toml::Value::String(string) => Some(Cow::Borrowed(string)),
+      toml::Value::Integer(number) => Some(Cow::Owned(number.to_string())),
+      toml::Value::Float(number) => Some(Cow::Owned(number.to_string())),
+      toml::Value::Boolean(flag) => Some(Cow::Borrowed(bool_to_str(*flag))),
+      toml::Value::Datetime(datetime) => Some(Cow::Owned(datetime.to_string())),
+      _ => None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[derive(Serialize)]
+  struct Person {
+    name: String,
+    tags: Vec<&'static str>,
+  }
+
+  #[test]
+  fn converted_structs() -> Result<()> {
+    let person = Person {
+      name: "Ada".to_string(),
+      tags: vec![],
+    };
+
+    let context = Converted::new(&person)?;
+
+    let name = context.get("name").and_then(|name| name.text());
+    assert_eq!(Some("Ada"), name.as_deref());
+    assert_eq!(Some(false), context.get("tags").map(|tags| tags.is_truthy()));
+    Ok(())
+  }
+
+  #[test]
+  fn json_truthiness() {
+    assert!(!json!(null).is_truthy());
+    assert!(!json!(false).is_truthy());
+    assert!(!json!([]).is_truthy());
+    assert!(json!("").is_truthy());
+    assert!(json!({}).is_truthy());
+  }
+
+  #[cfg(feature = "yaml")]
+  #[test]
+  fn yaml_values() {
+    let value: serde_yaml::Value = serde_yaml::from_str("count: 3\nitems: [a, b]").unwrap();
+
+    let count = value.get("count").and_then(|count| count.text());
+    assert_eq!(Some("3"), count.as_deref());
+    assert_eq!(Some(2), Context::get(&value, "items").and_then(|items| items.items()).map(Iterator::count));
+  }
+
+  #[cfg(feature = "toml")]
+  #[test]
+  fn toml_values() {
+    let value: toml::Value = toml::from_str("count = 3\nitems = []").unwrap();
+
+    let count = Context::get(&value, "count").and_then(|count| count.text());
+    assert_eq!(Some("3"), count.as_deref());
+    assert_eq!(Some(false), Context::get(&value, "items").map(|items| items.is_truthy()));
+  }
+}
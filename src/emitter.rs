@@ -1,38 +1,153 @@
-use std::io::{Result, Write};
-use v_htmlescape::escape;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::{fmt, io, mem};
+use typed_arena::Arena;
 
+use super::context::Context;
+use super::escape::Escaper;
+use super::lambda::Lambda;
+use super::loader::PartialLoader;
+use super::options::{Missing, Options};
 use super::resolver::resolve;
-use super::types::{ContextTag, Value, KeyTag, Tag, Template, ValueTag};
+use super::types::{
+  BlockTag, ContextTag, Error, KeySlice, KeyTag, Missed, ParentTag, PartialTag, Position, Result,
+  Tag, Template, Warning,
+};
 
-struct Emitter<'a, W, P>
-where
-  W: Write,
-  P: Fn(&str) -> Option<String>,
-{
-  writer: W,
-  context: Vec<&'a Value>,
-  partials: P,
+/// Somewhere rendered text goes, one piece at a time.
+pub trait Output {
+  fn write_str(&mut self, text: &str) -> Result<()>;
 }
 
+/// Output into an `io::Write`, such as a file or socket.
+pub struct IoOutput<W>(pub W);
+
+impl<W: io::Write> Output for IoOutput<W> {
+  fn write_str(&mut self, text: &str) -> Result<()> {
+    Ok(self.0.write_all(text.as_bytes())?)
+  }
+}
+
+/// Output into a `fmt::Write`, such as a `String` or `Formatter`.
+pub struct FmtOutput<W>(pub W);
+
+impl<W: fmt::Write> Output for FmtOutput<W> {
+  fn write_str(&mut self, text: &str) -> Result<()> {
+    self.0.write_str(text).map_err(|err| Error::Write(io::Error::new(io::ErrorKind::Other, err)))
+  }
+}
 
-fn bool_to_str(flag: bool) -> &'static str {
-  if flag {
-    "true"
+/// Block overrides in effect, by block name, with the indentation of the
+/// template that wrote them.
+type Blocks<'t> = HashMap<&'t str, (&'t BlockTag<'t>, Rc<Indentation>)>;
+
+/// The whitespace before the standalone partial tags that include the
+/// template being emitted, prefixed to each line it renders.
+#[derive(Default)]
+struct Indentation {
+  prefix: String,
+}
+
+/// Whether a tag starting at `start` opens a line that renders, which takes
+/// the indentation in effect. A standalone tag's line is stripped instead.
+pub(crate) fn opens_line(start: Position, standalone: bool) -> bool {
+  start.column == 1 && !standalone
+}
+
+/// The indentation a partial or parent adds, after the one around it, to the
+/// template it includes: its own when it stands alone on its line, or `None`
+/// when it shares a line that already carries the indentation around it.
+pub(crate) fn included_indentation(standalone: bool, own: &str) -> Option<&str> {
+  if standalone {
+    Some(own)
   } else {
-    "false"
+    None
   }
 }
 
-impl<'a, W, P> Emitter<'a, W, P>
+/// Partial sources and their parsed templates, kept for a whole render so that
+/// each partial is loaded and parsed once.
+struct PartialArenas<'p> {
+  sources: Arena<String>,
+  templates: Arena<Template<'p>>,
+}
+
+struct Emitter<'a, 'p, W, P>
+where
+  W: Output,
+  P: PartialLoader,
+{
+  writer: W,
+  context: Vec<&'a dyn Context>,
+  partials: P,
+  options: &'a Options<'a>,
+  /// The template's own escaper, or else the options'.
+  escaper: &'a dyn Escaper,
+  captures: Vec<String>,
+  warnings: Vec<Warning>,
+  /// Names of the partials being rendered, innermost last.
+  names: Vec<String>,
+  arenas: &'p PartialArenas<'p>,
+  sources: HashMap<String, Option<&'p str>>,
+  cache: HashMap<String, &'p Template<'p>>,
+  indentation: Rc<Indentation>,
+}
+
+impl<'a, 'p, W, P> Emitter<'a, 'p, W, P>
 where
-  W: Write,
-  P: Fn(&str) -> Option<String>,
+  W: Output,
+  P: PartialLoader,
 {
-  fn new(writer: W, partials: P) -> Emitter<'a, W, P> {
+  fn new(
+    writer: W,
+    partials: P,
+    options: &'a Options<'a>,
+    escaper: &'a dyn Escaper,
+    arenas: &'p PartialArenas<'p>,
+  ) -> Emitter<'a, 'p, W, P> {
+    // Lambdas from the options sit below the data, which shadows them.
+    let context = options.lambdas.map(|lambdas| lambdas as &dyn Context).into_iter().collect();
     Emitter {
       writer,
-      context: vec![],
+      context,
       partials,
+      options,
+      escaper,
+      captures: vec![],
+      warnings: vec![],
+      names: vec![],
+      arenas,
+      sources: HashMap::new(),
+      cache: HashMap::new(),
+      indentation: Rc::default(),
+    }
+  }
+
+  /// Resolves a key against the context stack, reporting a miss as the
+  /// options ask.
+  fn lookup(&mut self, key: &KeySlice, position: Position) -> Result<Option<&'a dyn Context>> {
+    if let Ok(value) = resolve(&self.context, key) {
+      return Ok(Some(value));
+    }
+    self.miss(Missed::Key, key.join("."), position)?;
+    Ok(None)
+  }
+
+  /// Reports a key or partial that resolved to nothing, as the options ask.
+  fn miss(&mut self, missed: Missed, key: String, position: Position) -> Result<()> {
+    match (self.options.missing, missed) {
+      (Missing::Ignore, _) => Ok(()),
+      (Missing::Warn, _) => {
+        self.warnings.push(Warning {
+          missed,
+          key,
+          position,
+          partial: self.names.last().cloned(),
+        });
+        Ok(())
+      }
+      (Missing::Fail, Missed::Key) => Err(Error::MissingKey { position, key }),
+      (Missing::Fail, Missed::Partial) => Err(Error::MissingPartial { position, name: key }),
     }
   }
 
@@ -41,97 +156,286 @@ where
   }
 
   fn emit_string(&mut self, text: &str) -> Result<()> {
-    self.writer.write_all(text.as_bytes())?;
+    match self.captures.last_mut() {
+      Some(capture) => capture.push_str(text),
+      None => self.writer.write_str(text)?,
+    }
     Ok(())
   }
 
-  fn emit_context(&mut self, tags: &[Tag], value: &'a Value) -> Result<()>
+  /// Starts a line that a tag opens with the indentation, if it renders.
+  fn emit_indentation(&mut self, start: Position, standalone: bool) -> Result<()> {
+    if !opens_line(start, standalone) {
+      return self.emit_nothing();
+    }
+    let indentation = self.indentation.clone();
+    self.emit_string(&indentation.prefix)
+  }
+
+  /// Text with the indentation before each line it starts.
+  fn emit_text(&mut self, value: &str, start: Position) -> Result<()> {
+    if self.indentation.prefix.is_empty() || value.is_empty() {
+      return self.emit_string(value);
+    }
+    let indentation = self.indentation.clone();
+    if start.column == 1 {
+      self.emit_string(&indentation.prefix)?;
+    }
+    let mut rest = value;
+    while let Some(index) = rest.find('\n').filter(|&index| index + 1 < rest.len()) {
+      self.emit_string(&rest[..=index])?;
+      self.emit_string(&indentation.prefix)?;
+      rest = &rest[index + 1..];
+    }
+    self.emit_string(rest)
+  }
+
+  /// Emits tags with another indentation in effect.
+  fn emit_indented<'t>(
+    &mut self,
+    indentation: Rc<Indentation>,
+    tags: &'t [Tag<'t>],
+    blocks: &Blocks<'t>,
+  ) -> Result<()> {
+    let outer = mem::replace(&mut self.indentation, indentation);
+    let result = self.emit_tags(tags, blocks);
+    self.indentation = outer;
+    result
+  }
+
+  fn emit_context<'t>(&mut self, tags: &'t [Tag<'t>], blocks: &Blocks<'t>, value: &'a dyn Context) -> Result<()>
   {
     self.context.push(value);
-    let result = self.emit_tags(tags);
+    let result = self.emit_tags(tags, blocks);
     self.context.pop();
     result
   }
 
-  fn emit_tags(&mut self, tags: &[Tag]) -> Result<()> {
+  /// The tags between an opening and a closing tag, and the indentation of
+  /// the line the closing tag opens, which renders with every pass.
+  fn emit_body<'t>(&mut self, tags: &'t [Tag<'t>], close: Position, standalone: bool, blocks: &Blocks<'t>) -> Result<()>
+  {
+    self.emit_tags(tags, blocks)?;
+    self.emit_indentation(close, standalone)
+  }
+
+  fn emit_tags<'t>(&mut self, tags: &'t [Tag<'t>], blocks: &Blocks<'t>) -> Result<()> {
     for tag in tags.iter() {
-      self.emit_tag(tag)?;
+      self.emit_tag(tag, blocks)?;
     }
     Ok(())
   }
 
-  fn emit_tag(&mut self, tag: &Tag) -> Result<()> {
+  fn emit_tag<'t>(&mut self, tag: &'t Tag<'t>, blocks: &Blocks<'t>) -> Result<()> {
+    if let Tag::Text(value) = tag {
+      return self.emit_text(value.value, value.span.start);
+    }
+    self.emit_indentation(tag.span().start, tag.standalone())?;
     match tag {
-      Tag::Text(value) => self.emit_string(&value.value),
-      Tag::Variable(key) => self.emit_variable(key),
-      Tag::Unescaped(key) => self.emit_unescaped(key),
-      Tag::Section(section) => self.emit_section(section),
-      Tag::Inverted(section) => self.emit_inverted(section),
-      Tag::Partial(value) => self.emit_partial(value),
+      Tag::Text(_) => self.emit_nothing(),
+      Tag::Variable(key) => self.emit_variable(key, blocks),
+      Tag::Unescaped(key) => self.emit_unescaped(key, blocks),
+      Tag::Section(section) => self.emit_section(section, blocks),
+      Tag::Inverted(section) => self.emit_inverted(section, blocks),
+      Tag::Partial(value) => self.emit_partial(value, blocks),
+      Tag::Parent(parent) => self.emit_parent(parent, blocks),
+      Tag::Block(block) => self.emit_block(block, blocks),
       _ => self.emit_nothing(),
     }
   }
 
-  fn emit_variable(&mut self, tag: &KeyTag) -> Result<()> {
-    match resolve(&self.context, &tag.key) {
-      Ok(Value::String(string)) => self.emit_string(&escape(string).to_string()),
-      Ok(Value::Number(number)) => self.emit_string(&number.to_string()),
-      Ok(Value::Bool(boolean)) => self.emit_string(bool_to_str(*boolean)),
-      _ => self.emit_nothing(),
+  fn emit_variable(&mut self, tag: &KeyTag, blocks: &Blocks) -> Result<()> {
+    let value = self.lookup(&tag.key, tag.span.start)?;
+    if let Some(lambda) = value.and_then(|value| value.lambda()) {
+      self.captures.push(String::new());
+      let result = self.emit_lambda(lambda, None, blocks);
+      let rendered = self.captures.pop().unwrap_or_default();
+      result?;
+      return self.emit_string(&self.escaper.escape(&rendered));
+    }
+    match value.and_then(|value| value.text()) {
+      Some(text) => self.emit_string(&self.escaper.escape(&text)),
+      None => self.emit_nothing(),
     }
   }
 
-  fn emit_unescaped(&mut self, tag: &KeyTag) -> Result<()> {
-    match resolve(&self.context, &tag.key) {
-      Ok(Value::String(string)) => self.emit_string(string),
-      Ok(Value::Number(number)) => self.emit_string(&number.to_string()),
-      Ok(Value::Bool(boolean)) => self.emit_string(bool_to_str(*boolean)),
-      _ => self.emit_nothing(),
+  fn emit_unescaped(&mut self, tag: &KeyTag, blocks: &Blocks) -> Result<()> {
+    let value = self.lookup(&tag.key, tag.span.start)?;
+    if let Some(lambda) = value.and_then(|value| value.lambda()) {
+      return self.emit_lambda(lambda, None, blocks);
+    }
+    match value.and_then(|value| value.text()) {
+      Some(text) => self.emit_string(&text),
+      None => self.emit_nothing(),
     }
   }
 
-  fn emit_section(&mut self, tag: &ContextTag) -> Result<()> {
-    match resolve(&self.context, &tag.key) {
-      Ok(Value::Null) => self.emit_nothing(),
-      Ok(Value::Bool(false)) => self.emit_nothing(),
-      Ok(Value::Array(vec)) if vec.is_empty() => self.emit_nothing(),
-      Ok(Value::Array(vec)) if !vec.is_empty() => {
-        for item in vec {
-          self.emit_context(&tag.tags, item)?;
+  fn emit_section<'t>(&mut self, tag: &'t ContextTag<'t>, blocks: &Blocks<'t>) -> Result<()> {
+    let value = self.lookup(&tag.key, tag.span.start)?;
+    if let Some(lambda) = value.and_then(|value| value.lambda()) {
+      return self.emit_lambda(lambda, Some(tag), blocks);
+    }
+    match value {
+      Some(value) if !value.is_truthy() => self.emit_nothing(),
+      Some(value) => match value.items() {
+        Some(items) => {
+          for item in items {
+            self.emit_context(&tag.tags, blocks, item)?;
+            self.emit_indentation(tag.close.start, tag.close_standalone)?;
+          }
+          Ok(())
         }
-        Ok(())
-      }
-      Ok(value) => self.emit_context(&tag.tags, &value),
-      _ => self.emit_nothing(),
+        None => {
+          self.emit_context(&tag.tags, blocks, value)?;
+          self.emit_indentation(tag.close.start, tag.close_standalone)
+        }
+      },
+      None => self.emit_nothing(),
     }
   }
 
-  fn emit_inverted(&mut self, tag: &ContextTag) -> Result<()> {
+  fn emit_inverted<'t>(&mut self, tag: &'t ContextTag<'t>, blocks: &Blocks<'t>) -> Result<()> {
     match resolve(&self.context, &tag.key) {
-      Ok(Value::Null) => self.emit_tags(&tag.tags),
-      Ok(Value::Bool(false)) => self.emit_tags(&tag.tags),
-      Ok(Value::Array(vec)) if vec.is_empty() => self.emit_tags(&tag.tags),
-      Ok(_) => self.emit_nothing(),
-      _ => self.emit_tags(&tag.tags),
+      Ok(value) if value.is_truthy() => self.emit_nothing(),
+      _ => self.emit_body(&tag.tags, tag.close.start, tag.close_standalone, blocks),
     }
   }
 
-  fn emit_partial(&mut self, tag: &ValueTag) -> Result<()> {
-    let key = tag.value.to_string();
-    if let Some(text) = (self.partials)(&key) {
-      if let Ok((template, _)) = crate::parser::template(&text) {
-        return self.emit_tags(&template.tags);
+  fn emit_lambda(&mut self, lambda: &Lambda, section: Option<&ContextTag>, blocks: &Blocks) -> Result<()> {
+    let (text, start, stop) = match (lambda, section) {
+      (Lambda::Section(lambda), Some(tag)) => (lambda(tag.source), tag.delimiters.start, tag.delimiters.stop),
+      (Lambda::Section(lambda), None) => (lambda(""), "{{", "}}"),
+      (Lambda::Interpolation(lambda), _) => (lambda(), "{{", "}}"),
+    };
+    // The expansion is the host's text, so like interpolated data it is not
+    // indented.
+    let (template, _) = crate::parser::template_with_delimiters(&text, start, stop)?;
+    self.emit_indented(Rc::default(), &template.tags, blocks)
+  }
+
+  fn emit_partial<'t>(&mut self, tag: &'t PartialTag<'t>, blocks: &Blocks<'t>) -> Result<()> {
+    let indentation = self.nested_indentation(tag.standalone, tag.indentation);
+    let key = match &tag.key {
+      Some(key) => key,
+      None => return self.emit_template(tag.name, indentation, blocks, tag.span.start),
+    };
+    match self.lookup(key, tag.span.start)?.and_then(|value| value.text()) {
+      Some(name) => self.emit_template(&name, indentation, blocks, tag.span.start),
+      None => self.emit_nothing(),
+    }
+  }
+
+  fn emit_parent<'t>(&mut self, tag: &'t ParentTag<'t>, blocks: &Blocks<'t>) -> Result<()> {
+    // Overrides from further out take precedence over the parent's own.
+    let mut scope = blocks.clone();
+    for child in tag.tags.iter() {
+      if let Tag::Block(block) = child {
+        scope.entry(block.name).or_insert((block, self.indentation.clone()));
+      }
+    }
+    let indentation = self.nested_indentation(tag.standalone, tag.indentation);
+    self.emit_template(tag.name, indentation, &scope, tag.span.start)
+  }
+
+  fn emit_block<'t>(&mut self, tag: &'t BlockTag<'t>, blocks: &Blocks<'t>) -> Result<()> {
+    match blocks.get(tag.name) {
+      Some((block, indentation)) => {
+        let outer = mem::replace(&mut self.indentation, indentation.clone());
+        let result = self.emit_body(&block.tags, block.close.start, block.close_standalone, blocks);
+        self.indentation = outer;
+        result
       }
+      None => self.emit_body(&tag.tags, tag.close.start, tag.close_standalone, blocks),
+    }
+  }
+
+  fn nested_indentation(&self, standalone: bool, own: &str) -> String {
+    match included_indentation(standalone, own) {
+      Some(own) => format!("{}{}", self.indentation.prefix, own),
+      None => String::new(),
+    }
+  }
+
+  fn load_source(&mut self, name: &str, position: Position) -> Result<Option<&'p str>> {
+    if let Some(source) = self.sources.get(name) {
+      return Ok(*source);
+    }
+    let source = self.partials.load(name).map_err(|error| Error::Load {
+      position,
+      name: name.to_string(),
+      error,
+    })?;
+    let source = source.map(|text| self.arenas.sources.alloc(text).as_str());
+    self.sources.insert(name.to_string(), source);
+    Ok(source)
+  }
+
+  fn load_template(&mut self, name: &str, position: Position) -> Result<Option<&'p Template<'p>>> {
+    if let Some(template) = self.cache.get(name) {
+      return Ok(Some(*template));
     }
+    let source = match self.load_source(name, position)? {
+      Some(source) => source,
+      None => return Ok(None),
+    };
 
-    self.emit_nothing()
+    let (template, _) = crate::parser::template(source).map_err(|error| Error::Partial {
+      position,
+      name: name.to_string(),
+      error: Box::new(error),
+    })?;
+    let template = &*self.arenas.templates.alloc(template);
+    self.cache.insert(name.to_string(), template);
+    Ok(Some(template))
+  }
+
+  /// Renders the named partial in place of the tag at `position`.
+  fn emit_template(&mut self, name: &str, prefix: String, blocks: &Blocks, position: Position) -> Result<()> {
+    let template = match self.load_template(name, position)? {
+      Some(found) => found,
+      None => return self.miss(Missed::Partial, name.to_string(), position),
+    };
+    if self.names.len() >= self.options.max_depth {
+      return Err(Error::DepthExceeded {
+        position,
+        name: name.to_string(),
+        depth: self.options.max_depth,
+      });
+    }
+    self.names.push(name.to_string());
+    let result = self.emit_indented(Rc::new(Indentation { prefix }), &template.tags, blocks);
+    self.names.pop();
+    result.map_err(|error| match error {
+      Error::Write(_) => error,
+      // Left unwrapped, so as not to nest it once per partial; it moves out
+      // to the tag that began the nesting instead.
+      Error::DepthExceeded { name, depth, .. } => Error::DepthExceeded { position, name, depth },
+      _ => Error::Partial {
+        position,
+        name: name.to_string(),
+        error: Box::new(error),
+      },
+    })
   }
 }
 
-pub fn emit<W, P>(writer: W, template: &Template, value: &Value, partials: P) -> Result<()>
+pub fn emit<W, P>(
+  writer: W,
+  template: &Template,
+  value: &dyn Context,
+  partials: P,
+  options: &Options,
+) -> Result<Vec<Warning>>
 where
-  W: Write,
-  P: Fn(&str) -> Option<String>,
+  W: Output,
+  P: PartialLoader,
 {
-  Emitter::new(writer, partials).emit_context(&template.tags, value)
+  let arenas = PartialArenas {
+    sources: Arena::new(),
+    templates: Arena::new(),
+  };
+  let escaper = template.escaper.unwrap_or(options.escaper);
+  let mut emitter = Emitter::new(writer, partials, options, escaper, &arenas);
+  emitter.emit_context(&template.tags, &Blocks::new(), value)?;
+  Ok(emitter.warnings)
 }
@@ -0,0 +1,198 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Escapes interpolated values for the format a template produces. Applies to
+/// `{{name}}` tags only; `{{{name}}}` and `{{&name}}` always emit raw text.
+pub trait Escaper {
+  fn escape<'t>(&self, text: &'t str) -> Cow<'t, str>;
+}
+
+impl<F> Escaper for F
+where
+  F: Fn(&str) -> String,
+{
+  fn escape<'t>(&self, text: &'t str) -> Cow<'t, str> {
+    Cow::Owned(self(text))
+  }
+}
+
+/// Rewrites the characters `replace` picks out, borrowing the text untouched
+/// when there are none.
+fn replace_chars<'t, F>(text: &'t str, replace: F) -> Cow<'t, str>
+where
+  F: Fn(char, &mut String) -> bool,
+{
+  let mut escaped = String::new();
+  for (index, c) in text.char_indices() {
+    if escaped.is_empty() {
+      let mut replacement = String::new();
+      if replace(c, &mut replacement) {
+        escaped.reserve(text.len() + replacement.len());
+        escaped.push_str(&text[..index]);
+        escaped.push_str(&replacement);
+      }
+    } else if !replace(c, &mut escaped) {
+      escaped.push(c);
+    }
+  }
+  if escaped.is_empty() {
+    Cow::Borrowed(text)
+  } else {
+    Cow::Owned(escaped)
+  }
+}
+
+/// HTML entities, the spec's default.
+pub struct Html;
+
+impl Escaper for Html {
+  fn escape<'t>(&self, text: &'t str) -> Cow<'t, str> {
+    Cow::Owned(v_htmlescape::escape(text).to_string())
+  }
+}
+
+/// Emits values untouched.
+pub struct NoEscape;
+
+impl Escaper for NoEscape {
+  fn escape<'t>(&self, text: &'t str) -> Cow<'t, str> {
+    Cow::Borrowed(text)
+  }
+}
+
+/// Escapes for the inside of a JSON string literal; the template supplies the
+/// surrounding quotes.
+pub struct Json;
+
+impl Escaper for Json {
+  fn escape<'t>(&self, text: &'t str) -> Cow<'t, str> {
+    replace_chars(text, |c, out| {
+      match c {
+        '"' => out.push_str("\\\""),
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\u{8}' => out.push_str("\\b"),
+        '\u{c}' => out.push_str("\\f"),
+        c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+        _ => return false,
+      }
+      true
+    })
+  }
+}
+
+/// Quotes each value as a single shell word.
+pub struct Shell;
+
+impl Escaper for Shell {
+  fn escape<'t>(&self, text: &'t str) -> Cow<'t, str> {
+    Cow::Owned(format!("'{}'", text.replace('\'', "'\\''")))
+  }
+}
+
+/// Escapes LaTeX's special characters for use in running text.
+pub struct Latex;
+
+impl Escaper for Latex {
+  fn escape<'t>(&self, text: &'t str) -> Cow<'t, str> {
+    replace_chars(text, |c, out| {
+      match c {
+        '\\' => out.push_str("\\textbackslash{}"),
+        '~' => out.push_str("\\textasciitilde{}"),
+        '^' => out.push_str("\\textasciicircum{}"),
+        '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+          out.push('\\');
+          out.push(c);
+        }
+        _ => return false,
+      }
+      true
+    })
+  }
+}
+
+/// Makes each value a single CSV field, quoting it only when it needs to be.
+pub struct Csv;
+
+impl Escaper for Csv {
+  fn escape<'t>(&self, text: &'t str) -> Cow<'t, str> {
+    if text.contains(&[',', '"', '\n', '\r'][..]) {
+      Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+      Cow::Borrowed(text)
+    }
+  }
+}
+
+/// Escapers by name, so that each template can pick the one its output needs.
+/// Starts with the built-ins: `html`, `none`, `json`, `shell`, `latex` and
+/// `csv`.
+pub struct Escapers {
+  escapers: HashMap<String, Box<dyn Escaper>>,
+}
+
+impl Default for Escapers {
+  fn default() -> Escapers {
+    Escapers::new()
+      .register("html", Html)
+      .register("none", NoEscape)
+      .register("json", Json)
+      .register("shell", Shell)
+      .register("latex", Latex)
+      .register("csv", Csv)
+  }
+}
+
+impl Escapers {
+  /// A registry without any escapers, not even the built-ins.
+  pub fn new() -> Escapers {
+    Escapers {
+      escapers: HashMap::new(),
+    }
+  }
+
+  pub fn register<E>(mut self, name: &str, escaper: E) -> Escapers
+  where
+    E: Escaper + 'static,
+  {
+    self.escapers.insert(name.to_string(), Box::new(escaper));
+    self
+  }
+
+  pub fn get(&self, name: &str) -> Option<&dyn Escaper> {
+    self.escapers.get(name).map(|escaper| escaper.as_ref())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn builtin_escapers() {
+    assert_eq!("a &amp; &lt;b&gt;", Html.escape("a & <b>"));
+    assert_eq!("a & <b>", NoEscape.escape("a & <b>"));
+    assert_eq!("say \\\"hi\\\"\\n\\u0001", Json.escape("say \"hi\"\n\u{1}"));
+    assert_eq!("'it'\\''s'", Shell.escape("it's"));
+    assert_eq!("50\\% \\$x\\_1 \\textbackslash{}", Latex.escape("50% $x_1 \\"));
+    assert_eq!("\"a, \"\"b\"\"\"", Csv.escape("a, \"b\""));
+  }
+
+  #[test]
+  fn unchanged_text_is_borrowed() {
+    assert!(matches!(Json.escape("plain é"), Cow::Borrowed(_)));
+    assert!(matches!(Latex.escape("plain é"), Cow::Borrowed(_)));
+    assert!(matches!(Csv.escape("plain é"), Cow::Borrowed(_)));
+  }
+
+  #[test]
+  fn registered_escapers() {
+    let escapers = Escapers::default().register("upper", |text: &str| text.to_uppercase());
+
+    assert_eq!("LOUD", escapers.get("upper").unwrap().escape("loud"));
+    assert_eq!("&lt;", escapers.get("html").unwrap().escape("<"));
+    assert!(escapers.get("yaml").is_none());
+  }
+}
@@ -0,0 +1,365 @@
+use std::fmt;
+
+use super::owned::OwnedTemplate;
+use super::parser;
+use super::types::{Result, Span, Tag, Template};
+
+/// How a formatter writes the space inside tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spacing {
+  /// As written.
+  Keep,
+  /// `{{name}}`
+  Tight,
+  /// `{{ name }}`
+  Padded,
+}
+
+/// Rewrites template source in one consistent style. Only the space inside
+/// tags and the indentation of standalone tags change, neither of which the
+/// parser keeps, so the result parses to the same tree, apart from the raw
+/// section text handed to lambdas. By default the source comes back exactly as
+/// it was.
+#[derive(Debug, Clone)]
+pub struct Formatter {
+  spacing: Spacing,
+  indent: Option<String>,
+}
+
+impl Default for Formatter {
+  fn default() -> Formatter {
+    Formatter {
+      spacing: Spacing::Keep,
+      indent: None,
+    }
+  }
+}
+
+/// A tag as it sits in the source, with the opening and closing tags of a
+/// section as separate pieces.
+struct Piece<'a> {
+  span: Span,
+  start: &'a str,
+  stop: &'a str,
+  sigil: &'static str,
+  content: String,
+  /// Closes `{{{name}}}` before the stop delimiter.
+  closer: &'static str,
+  depth: usize,
+  /// Whether the indentation is free to change when the tag stands alone.
+  /// Partials and parents apply theirs to what they include.
+  reindent: bool,
+  /// Where the line of a standalone tag begins, for the tag leading it.
+  line_start: Option<usize>,
+}
+
+struct Collector<'a> {
+  source: &'a str,
+  start: &'a str,
+  stop: &'a str,
+  pieces: Vec<Piece<'a>>,
+}
+
+fn key_text(key: &[&str]) -> String {
+  if key.is_empty() {
+    ".".to_string()
+  } else {
+    key.join(".")
+  }
+}
+
+impl<'a> Collector<'a> {
+  fn push(&mut self, span: Span, standalone: bool, sigil: &'static str, content: String, depth: usize) {
+    let line_start = self.source[..span.start.offset].rfind('\n').map_or(0, |i| i + 1);
+    // Parent and block tags may share a standalone line, which the first leads.
+    let leads = standalone && !matches!(self.pieces.last(), Some(last) if last.span.end.offset > line_start);
+    self.pieces.push(Piece {
+      span,
+      start: self.start,
+      stop: self.stop,
+      sigil,
+      content,
+      closer: "",
+      depth,
+      reindent: sigil != ">" && sigil != "<",
+      line_start: if leads { Some(line_start) } else { None },
+    });
+  }
+
+  fn collect(&mut self, tags: &[Tag<'a>], depth: usize) {
+    for tag in tags {
+      match tag {
+        Tag::Text(_) => (),
+        Tag::Variable(variable) => self.push(variable.span, false, "", key_text(&variable.key), depth),
+        Tag::Unescaped(variable) => {
+          let written = &self.source[variable.span.range()][self.start.len()..];
+          if written.starts_with('{') {
+            self.push(variable.span, false, "{", key_text(&variable.key), depth);
+            if let Some(piece) = self.pieces.last_mut() {
+              piece.closer = "}";
+            }
+          } else {
+            self.push(variable.span, false, "&", key_text(&variable.key), depth);
+          }
+        }
+        Tag::Section(section) | Tag::Inverted(section) => {
+          let sigil = if matches!(tag, Tag::Section(_)) { "#" } else { "^" };
+          self.push(section.span, section.standalone, sigil, key_text(&section.key), depth);
+          self.collect(&section.tags, depth + 1);
+          self.push(section.close, section.close_standalone, "/", key_text(&section.key), depth);
+        }
+        Tag::Comment(comment) => {
+          self.push(comment.span, comment.standalone, "!", comment.value.to_string(), depth);
+        }
+        Tag::Partial(partial) => {
+          let name = match partial.key {
+            Some(_) => format!("*{}", partial.name),
+            None => partial.name.to_string(),
+          };
+          self.push(partial.span, partial.standalone, ">", name, depth);
+        }
+        Tag::Parent(parent) => {
+          self.push(parent.span, parent.standalone, "<", parent.name.to_string(), depth);
+          self.collect(&parent.tags, depth + 1);
+          self.push(parent.close, parent.close_standalone, "/", parent.name.to_string(), depth);
+        }
+        Tag::Block(block) => {
+          self.push(block.span, block.standalone, "$", block.name.to_string(), depth);
+          self.collect(&block.tags, depth + 1);
+          self.push(block.close, block.close_standalone, "/", block.name.to_string(), depth);
+        }
+        Tag::Delimiters(delimiters) => {
+          let content = format!("{} {}", delimiters.start, delimiters.stop);
+          self.push(delimiters.span, delimiters.standalone, "=", content, depth);
+          self.start = delimiters.start;
+          self.stop = delimiters.stop;
+        }
+      }
+    }
+  }
+}
+
+impl Formatter {
+  pub fn new() -> Formatter {
+    Formatter::default()
+  }
+
+  pub fn spacing(mut self, spacing: Spacing) -> Formatter {
+    self.spacing = spacing;
+    self
+  }
+
+  /// Indents each standalone section, block and comment tag by this much per
+  /// enclosing section.
+  pub fn indent(mut self, unit: &str) -> Formatter {
+    self.indent = Some(unit.to_string());
+    self
+  }
+
+  fn write_tag(&self, out: &mut String, source: &str, piece: &Piece) {
+    let pad = match self.spacing {
+      Spacing::Keep => {
+        out.push_str(&source[piece.span.range()]);
+        return;
+      }
+      Spacing::Padded if piece.sigil != "=" && !piece.content.is_empty() => " ",
+      _ => "",
+    };
+    out.push_str(piece.start);
+    out.push_str(piece.sigil);
+    out.push_str(pad);
+    out.push_str(&piece.content);
+    out.push_str(pad);
+    if piece.sigil == "=" {
+      out.push('=');
+    }
+    out.push_str(piece.closer);
+    out.push_str(piece.stop);
+  }
+
+  /// Formats template source, failing only if it does not parse.
+  pub fn format(&self, source: &str) -> Result<String> {
+    let (template, _) = parser::template(source)?;
+    let mut collector = Collector {
+      source,
+      start: "{{",
+      stop: "}}",
+      pieces: vec![],
+    };
+    collector.collect(&template.tags, 0);
+    let pieces = collector.pieces;
+
+    let mut out = String::with_capacity(source.len());
+    let mut at = 0;
+    for piece in pieces.iter() {
+      match (&self.indent, piece.line_start) {
+        (Some(unit), Some(line_start)) if piece.reindent => {
+          out.push_str(&source[at..line_start]);
+          out.push_str(&unit.repeat(piece.depth));
+        }
+        _ => out.push_str(&source[at..piece.span.start.offset]),
+      }
+      self.write_tag(&mut out, source, piece);
+      at = piece.span.end.offset;
+    }
+    out.push_str(&source[at..]);
+    Ok(out)
+  }
+}
+
+/// Writes a template back out as source, one tag at a time.
+struct Printer<'t> {
+  start: &'t str,
+  stop: &'t str,
+  out: String,
+}
+
+impl<'t> Printer<'t> {
+  /// A tag that stands alone gets a line of its own, which the parser strips
+  /// again, with the indentation of a partial or parent in front.
+  fn tag(&mut self, standalone: bool, sigil: &str, content: &str, indentation: &str) {
+    self.out.push_str(indentation);
+    self.out.push_str(self.start);
+    self.out.push_str(sigil);
+    self.out.push_str(content);
+    self.out.push_str(self.stop);
+    if standalone {
+      self.out.push('\n');
+    }
+  }
+
+  fn print(&mut self, tags: &[Tag<'t>]) {
+    for tag in tags {
+      match tag {
+        Tag::Text(text) => self.out.push_str(text.value),
+        Tag::Variable(variable) => self.tag(false, "", &key_text(&variable.key), ""),
+        Tag::Unescaped(variable) => self.tag(false, "&", &key_text(&variable.key), ""),
+        Tag::Section(section) | Tag::Inverted(section) => {
+          let sigil = if matches!(tag, Tag::Section(_)) { "#" } else { "^" };
+          self.tag(section.standalone, sigil, &key_text(&section.key), "");
+          self.print(&section.tags);
+          self.tag(section.close_standalone, "/", &key_text(&section.key), "");
+        }
+        Tag::Comment(comment) => self.tag(comment.standalone, "!", comment.value, ""),
+        Tag::Partial(partial) => {
+          let sigil = if partial.key.is_some() { ">*" } else { ">" };
+          self.tag(partial.standalone, sigil, partial.name, partial.indentation);
+        }
+        Tag::Parent(parent) => {
+          self.tag(parent.standalone, "<", parent.name, parent.indentation);
+          self.print(&parent.tags);
+          self.tag(parent.close_standalone, "/", parent.name, "");
+        }
+        Tag::Block(block) => {
+          self.tag(block.standalone, "$", block.name, "");
+          self.print(&block.tags);
+          self.tag(block.close_standalone, "/", block.name, "");
+        }
+        Tag::Delimiters(delimiters) => {
+          let content = format!("{} {}=", delimiters.start, delimiters.stop);
+          self.tag(delimiters.standalone, "=", &content, "");
+          self.start = delimiters.start;
+          self.stop = delimiters.stop;
+        }
+      }
+    }
+  }
+}
+
+/// Prints the template back to source that parses to the same tree.
+impl<'a> fmt::Display for Template<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut printer = Printer {
+      start: "{{",
+      stop: "}}",
+      out: String::new(),
+    };
+    printer.print(&self.tags);
+    f.write_str(&printer.out)
+  }
+}
+
+impl fmt::Display for OwnedTemplate {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.template().fmt(f)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Template;
+  use serde_json::Value;
+
+  /// The parsed tree without its spans and raw section text, which formatting
+  /// rewrites.
+  fn tree(source: &str) -> Value {
+    fn strip(value: &mut Value) {
+      match value {
+        Value::Object(map) => {
+          map.remove("span");
+          map.remove("close");
+          map.remove("source");
+          map.values_mut().for_each(strip);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip),
+        _ => (),
+      }
+    }
+    let owned = Template::compile(source).unwrap().to_owned_template();
+    let mut value = serde_json::to_value(owned).unwrap();
+    strip(&mut value);
+    value
+  }
+
+  const SOURCE: &str = "{{! header }}\n{{#items}}\n      {{^ empty}}\n{{{ name }}} {{&kind}}: {{  price.amount}}\n  {{/empty}}\n\
+    {{/items }}\r\n  {{>row}}\n{{<layout}}\n{{$body}}{{.}}{{/body}}\n    {{/layout}}\n{{=<% %>=}}\n<%#a%><%/a%>\n<%={{ }}=%>";
+
+  #[test]
+  fn keeps_source_by_default() -> Result<()> {
+    assert_eq!(SOURCE, Formatter::new().format(SOURCE)?);
+    Ok(())
+  }
+
+  #[test]
+  fn normalizes_spacing_and_indentation() -> Result<()> {
+    let formatted = Formatter::new().spacing(Spacing::Padded).indent("  ").format(SOURCE)?;
+
+    assert_eq!(
+      "{{! header }}\n{{# items }}\n  {{^ empty }}\n{{{ name }}} {{& kind }}: {{ price.amount }}\n  {{/ empty }}\n\
+       {{/ items }}\r\n  {{> row }}\n{{< layout }}\n{{$ body }}{{ . }}{{/ body }}\n{{/ layout }}\n{{=<% %>=}}\n\
+       <%# a %><%/ a %>\n<%={{ }}=%>",
+      formatted
+    );
+    assert_eq!(tree(SOURCE), tree(&formatted));
+
+    let tight = Formatter::new().spacing(Spacing::Tight).format(&formatted)?;
+    assert!(tight.contains("{{{name}}} {{&kind}}: {{price.amount}}"));
+    assert_eq!(tree(SOURCE), tree(&tight));
+    Ok(())
+  }
+
+  #[test]
+  fn leaves_tags_sharing_a_line_with_text() -> Result<()> {
+    let source = "{{#a}}{{b}}{{/a}}\n  {{#c}} x {{/c}}\n";
+    let formatted = Formatter::new().indent("    ").format(source)?;
+
+    assert_eq!(source, formatted);
+    Ok(())
+  }
+
+  #[test]
+  fn prints_templates_that_parse_to_the_same_tree() {
+    for source in [
+      SOURCE,
+      "{{#a}}\n  x\n{{/a}}\n{{! gone }}  \n  \ny",
+      "  {{>row}}\n{{#a}}{{/a}}\n  {{<layout}}{{/layout}}\n{{#b}}\n{{/b}}",
+    ] {
+      let printed = Template::compile(source).unwrap().to_string();
+      assert_eq!(tree(source), tree(&printed), "{:?} printed as {:?}", source, printed);
+    }
+
+    let owned = Template::compile("{{=| |=}}|#a||/a|\n").unwrap().to_owned_template();
+    assert_eq!("{{=| |=}}|#a||/a|\n", owned.to_string());
+  }
+}
@@ -0,0 +1,361 @@
+use std::collections::HashSet;
+
+use super::emitter::{included_indentation, opens_line};
+use super::types::{BlockTag, KeySlice, Position, Tag, Template};
+
+/// Helpers every generated module carries, mirroring the resolver, `Context`
+/// for JSON values, and the HTML escaper.
+const RUNTIME: &str = r#"const has = Object.prototype.hasOwnProperty;
+
+function get(value, name) {
+  if (value === null || typeof value !== "object" || Array.isArray(value) || !has.call(value, name)) {
+    return undefined;
+  }
+  return value[name] === undefined ? null : value[name];
+}
+
+function lookup(stack, key) {
+  for (let index = stack.length - 1; index >= 0; index--) {
+    if (key.length === 0 || get(stack[index], key[0]) !== undefined) {
+      let value = stack[index];
+      for (const name of key) {
+        value = get(value, name);
+      }
+      return value;
+    }
+  }
+  return undefined;
+}
+
+function truthy(value) {
+  if (Array.isArray(value)) {
+    return value.length > 0;
+  }
+  return value !== undefined && value !== null && value !== false;
+}
+
+function text(value) {
+  switch (typeof value) {
+    case "string":
+      return value;
+    case "number":
+    case "boolean":
+      return String(value);
+    default:
+      return "";
+  }
+}
+
+const entities = { "<": "&lt;", ">": "&gt;", "&": "&amp;", '"': "&quot;", "'": "&#x27;", "/": "&#x2f;" };
+
+function escape(text) {
+  return text.replace(/[<>&"'\/]/g, (c) => entities[c]);
+}
+
+function* each(stack, value) {
+  if (!truthy(value)) {
+    return;
+  }
+  for (const item of Array.isArray(value) ? value : [value]) {
+    stack.push(item === undefined ? null : item);
+    yield;
+    stack.pop();
+  }
+}
+
+function partial(partials, name, stack, blocks, indent, depth) {
+  const template = has.call(partials, name) ? partials[name] : undefined;
+  if (typeof template?.render !== "function") {
+    return "";
+  }
+  if (depth >= 100) {
+    throw new Error(`partial '${name}' is nested more than 100 deep`);
+  }
+  return template.render(stack, partials, blocks, indent, depth + 1);
+}
+
+function block(blocks, name, own) {
+  return (has.call(blocks, name) ? blocks[name] : own)(blocks);
+}
+"#;
+
+fn literal(text: &str) -> String {
+  serde_json::to_string(text).unwrap_or_default()
+}
+
+fn key(key: &KeySlice) -> String {
+  serde_json::to_string(key).unwrap_or_default()
+}
+
+/// The indentation argument a partial or parent passes, in terms of `i`, the
+/// indentation in effect.
+fn indentation(standalone: bool, own: &str) -> String {
+  match included_indentation(standalone, own) {
+    Some("") => "i".to_string(),
+    Some(own) => format!("i + {}", literal(own)),
+    None => literal(""),
+  }
+}
+
+struct Generator {
+  code: String,
+  depth: usize,
+}
+
+impl Generator {
+  fn line(&mut self, line: &str) {
+    for _ in 0..self.depth {
+      self.code.push_str("  ");
+    }
+    self.code.push_str(line);
+    self.code.push('\n');
+  }
+
+  fn open(&mut self, line: &str) {
+    self.line(line);
+    self.depth += 1;
+  }
+
+  fn close(&mut self, line: &str) {
+    self.depth -= 1;
+    self.line(line);
+  }
+
+  /// Indents a line that a tag opens.
+  fn indent(&mut self, start: Position, standalone: bool) {
+    if opens_line(start, standalone) {
+      self.line("o += i;");
+    }
+  }
+
+  /// Text with the indentation before each line it starts.
+  fn text(&mut self, value: &str, start: Position) {
+    let mut parts = vec![];
+    if start.column == 1 {
+      parts.push("i".to_string());
+    }
+    let mut rest = value;
+    while let Some(index) = rest.find('\n').filter(|&index| index + 1 < rest.len()) {
+      parts.push(literal(&rest[..=index]));
+      parts.push("i".to_string());
+      rest = &rest[index + 1..];
+    }
+    parts.push(literal(rest));
+    self.line(&format!("o += {};", parts.join(" + ")));
+  }
+
+  /// Statements appending each tag's output to `o`.
+  fn tags(&mut self, tags: &[Tag]) {
+    for tag in tags.iter() {
+      self.tag(tag);
+    }
+  }
+
+  /// A closure over the blocks in effect that returns what a block's tags
+  /// render, up to its closing tag.
+  fn closure(&mut self, head: &str, block: &BlockTag, tail: &str) {
+    self.open(&format!("{}(b) => {{", head));
+    self.line("let o = \"\";");
+    self.tags(&block.tags);
+    self.indent(block.close.start, block.close_standalone);
+    self.line("return o;");
+    self.close(&format!("}}{}", tail));
+  }
+
+  fn tag(&mut self, tag: &Tag) {
+    match tag {
+      Tag::Text(text) if text.value.is_empty() => (),
+      Tag::Text(text) => self.text(text.value, text.span.start),
+      Tag::Variable(tag) => {
+        self.indent(tag.span.start, false);
+        self.line(&format!("o += escape(text(lookup(s, {})));", key(&tag.key)));
+      }
+      Tag::Unescaped(tag) => {
+        self.indent(tag.span.start, false);
+        self.line(&format!("o += text(lookup(s, {}));", key(&tag.key)));
+      }
+      Tag::Section(section) => {
+        self.indent(section.span.start, section.standalone);
+        self.open(&format!("for (const _ of each(s, lookup(s, {}))) {{", key(&section.key)));
+        self.tags(&section.tags);
+        self.indent(section.close.start, section.close_standalone);
+        self.close("}");
+      }
+      Tag::Inverted(section) => {
+        self.indent(section.span.start, section.standalone);
+        self.open(&format!("if (!truthy(lookup(s, {}))) {{", key(&section.key)));
+        self.tags(&section.tags);
+        self.indent(section.close.start, section.close_standalone);
+        self.close("}");
+      }
+      Tag::Partial(partial) => {
+        self.indent(partial.span.start, partial.standalone);
+        let name = match &partial.key {
+          Some(name) => format!("text(lookup(s, {}))", key(name)),
+          None => literal(partial.name),
+        };
+        let indentation = indentation(partial.standalone, partial.indentation);
+        self.line(&format!("o += partial(p, {}, s, b, {}, d);", name, indentation));
+      }
+      Tag::Parent(parent) => {
+        self.indent(parent.span.start, parent.standalone);
+        let indentation = indentation(parent.standalone, parent.indentation);
+        self.open(&format!("o += partial(p, {}, s, Object.assign({{", literal(parent.name)));
+        let mut seen = HashSet::new();
+        for child in parent.tags.iter() {
+          // The first override of a name wins, as in the emitter.
+          if let Tag::Block(block) = child {
+            if seen.insert(block.name) {
+              self.closure(&format!("[{}]: ", literal(block.name)), block, ",");
+            }
+          }
+        }
+        self.close(&format!("}}, b), {}, d);", indentation));
+      }
+      Tag::Block(block) => {
+        self.indent(block.span.start, block.standalone);
+        let head = format!("o += block(b, {}, ", literal(block.name));
+        self.closure(&head, block, ");");
+      }
+      Tag::Comment(comment) => self.indent(comment.span.start, comment.standalone),
+      Tag::Delimiters(delimiters) => self.indent(delimiters.span.start, delimiters.standalone),
+    }
+  }
+}
+
+impl<'a> Template<'a> {
+  /// Generates a standalone JavaScript module that renders the template.
+  /// Its default export takes the data and an object of partials, each the
+  /// default export of another generated module, and renders as the emitter
+  /// does with HTML escaping, except that:
+  ///
+  /// - partials nest at most 100 deep, whatever `Options::max_depth` says;
+  /// - numbers print as JavaScript prints them, so `1.0` and `-0.0` come out
+  ///   as `1` and `0`;
+  /// - there are no lambdas, and functions in the data render as nothing.
+  pub fn javascript(&self) -> String {
+    let mut generator = Generator {
+      code: String::from(RUNTIME),
+      depth: 0,
+    };
+    generator.line("");
+    generator.open("function render(s, p, b, i, d) {");
+    generator.line("let o = \"\";");
+    generator.tags(&self.tags);
+    generator.line("return o;");
+    generator.close("}");
+    generator.line("");
+    generator.open("export default function template(data, partials = {}) {");
+    generator.line("return render([data === undefined ? null : data], partials, {}, \"\", 0);");
+    generator.close("}");
+    generator.line("");
+    generator.line("template.render = render;");
+    generator.code
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn render_function(source: &str) -> String {
+    let code = Template::compile(source).unwrap().javascript();
+    let start = code.find("function render").unwrap();
+    let end = code.find("\nexport default").unwrap();
+    code[start..end].to_string()
+  }
+
+  #[test]
+  fn sections_loop_and_inverted_sections_branch() {
+    let expected = r#"function render(s, p, b, i, d) {
+  let o = "";
+  o += i + "Hello ";
+  o += escape(text(lookup(s, ["name"])));
+  o += "!\n";
+  for (const _ of each(s, lookup(s, ["items"]))) {
+    o += i;
+    o += text(lookup(s, []));
+    o += "\n";
+  }
+  o += i;
+  if (!truthy(lookup(s, ["items"]))) {
+    o += "none";
+  }
+  return o;
+}
+"#;
+    assert_eq!(expected, render_function("Hello {{name}}!\n{{#items}}\n{{{.}}}\n{{/items}}\n{{^items}}none{{/items}}"));
+  }
+
+  #[test]
+  fn standalone_partials_take_the_indentation() {
+    let code = render_function("{{>a}}\n  {{>b}}\nx {{>c}}\n{{>*d}}");
+    assert!(code.contains(r#"o += partial(p, "a", s, b, i, d);"#));
+    assert!(code.contains(r#"o += partial(p, "b", s, b, i + "  ", d);"#));
+    assert!(code.contains(r#"o += i + "x ";"#));
+    assert!(code.contains(r#"o += partial(p, "c", s, b, "", d);"#));
+    assert!(code.contains(r#"o += partial(p, text(lookup(s, ["d"])), s, b, i, d);"#));
+  }
+
+  #[test]
+  fn the_first_override_of_a_block_wins() {
+    let code = render_function("{{<page}}{{$title}}x{{/title}}{{$title}}y{{/title}}{{/page}}\n{{$body}}\ndefault\n{{/body}}\n");
+    assert_eq!(1, code.matches(r#"["title"]: (b) => {"#).count());
+    assert!(code.contains(r#"o += "x";"#));
+    assert!(!code.contains(r#"o += "y";"#));
+    assert!(code.contains(r#"o += block(b, "body", (b) => {"#));
+  }
+
+  #[test]
+  fn modules_export_the_template() {
+    let code = Template::compile("{{a}}").unwrap().javascript();
+    assert!(code.contains("export default function template(data, partials = {}) {"));
+    assert!(code.ends_with("template.render = render;\n"));
+  }
+
+  /// Renders through node, or `None` when node is not installed.
+  fn node_render(source: &str, data: &serde_json::Value, partials: &[(&str, &str)]) -> Option<String> {
+    let dir = std::env::temp_dir().join(format!("mustache-javascript-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut script = String::from("import template from \"./template.mjs\";\nconst partials = {};\n");
+    std::fs::write(dir.join("template.mjs"), Template::compile(source).unwrap().javascript()).unwrap();
+    for (index, (name, partial)) in partials.iter().enumerate() {
+      let module = format!("partial{}.mjs", index);
+      std::fs::write(dir.join(&module), Template::compile(partial).unwrap().javascript()).unwrap();
+      script += &format!("partials[{:?}] = (await import(\"./{}\")).default;\n", name, module);
+    }
+    script += &format!("process.stdout.write(template({}, partials));\n", data);
+    std::fs::write(dir.join("run.mjs"), script).unwrap();
+    let output = std::process::Command::new("node").arg(dir.join("run.mjs")).output();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let output = output.ok()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    Some(String::from_utf8(output.stdout).unwrap())
+  }
+
+  #[test]
+  fn modules_render_as_the_emitter_does_under_node() {
+    let source = "<h1>{{title}}</h1>\n{{#items}}\n  {{>row}}\n{{/items}}\n{{^items}}none{{/items}}\n\
+      {{<layout}}{{$body}}{{count}} & {{{raw}}}{{/body}}{{/layout}}\n";
+    let partials = [
+      ("row", "<li>{{name}}\n{{#tags}}\n{{.}},\n{{/tags}}</li>\n"),
+      ("layout", "[{{$body}}default{{/body}}]"),
+    ];
+    let data = serde_json::json!({
+      "title": "A & B",
+      "items": [{ "name": "<x>", "tags": ["a", "b"] }, { "name": "y", "tags": [] }],
+      "count": 3,
+      "raw": "<b>",
+    });
+
+    let rendered = match node_render(source, &data, &partials) {
+      Some(rendered) => rendered,
+      None => {
+        eprintln!("skipping: node is not installed");
+        return;
+      }
+    };
+    let lookup = |name: &str| partials.iter().find(|(key, _)| *key == name).map(|(_, partial)| partial.to_string());
+    assert_eq!(Template::compile(source).unwrap().render(&data, lookup).unwrap(), rendered);
+  }
+}
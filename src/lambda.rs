@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::context::Context;
+
+/// A host function standing in for a value of the render context. Whatever
+/// template text it returns is rendered in place of the tag. Any `Context`
+/// can hold one, so lambdas resolve like other values: a nearer section
+/// shadows them, and each item of a list may carry its own.
+pub enum Lambda {
+  /// Called for `{{name}}` and `{{{name}}}`. The result is rendered with the
+  /// default delimiters.
+  Interpolation(Box<dyn Fn() -> String>),
+  /// Called with the unprocessed body of `{{#name}}...{{/name}}`. The result
+  /// is rendered with the delimiters in effect at the section.
+  Section(Box<dyn Fn(&str) -> String>),
+}
+
+/// Lambdas by name, as a context of their own. A render given lambdas in its
+/// options resolves keys against the data first, then against the lambdas.
+#[derive(Default)]
+pub struct Lambdas {
+  lambdas: HashMap<String, Lambda>,
+}
+
+impl Lambdas {
+  pub fn new() -> Lambdas {
+    Lambdas::default()
+  }
+
+  pub fn interpolation<F>(mut self, name: &str, lambda: F) -> Lambdas
+  where
+    F: Fn() -> String + 'static,
+  {
+    self.lambdas.insert(name.to_string(), Lambda::Interpolation(Box::new(lambda)));
+    self
+  }
+
+  pub fn section<F>(mut self, name: &str, lambda: F) -> Lambdas
+  where
+    F: Fn(&str) -> String + 'static,
+  {
+    self.lambdas.insert(name.to_string(), Lambda::Section(Box::new(lambda)));
+    self
+  }
+}
+
+impl Context for Lambda {
+  fn get(&self, _: &str) -> Option<&dyn Context> {
+    None
+  }
+
+  fn is_truthy(&self) -> bool {
+    true
+  }
+
+  fn text(&self) -> Option<Cow<'_, str>> {
+    None
+  }
+
+  fn lambda(&self) -> Option<&Lambda> {
+    Some(self)
+  }
+}
+
+impl Context for Lambdas {
+  fn get(&self, name: &str) -> Option<&dyn Context> {
+    self.lambdas.get(name).map(|lambda| lambda as &dyn Context)
+  }
+
+  fn is_truthy(&self) -> bool {
+    true
+  }
+
+  fn text(&self) -> Option<Cow<'_, str>> {
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{Options, Template};
+  use serde_json::json;
+
+  #[test]
+  fn nearer_data_shadows_a_lambda() {
+    let lambdas = Lambdas::new().interpolation("name", || "lambda".to_string());
+    let options = Options::new().lambdas(&lambdas);
+    let template = Template::compile("{{name}} {{#user}}{{name}}{{/user}}").unwrap();
+    let rendered = template.render_with(&json!({ "user": { "name": "data" } }), |_: &str| None, &options);
+    assert_eq!("lambda data", rendered.unwrap().output);
+  }
+
+  /// A list whose items each carry their own lambda.
+  struct Items(Vec<Item>);
+
+  struct Item(Lambda);
+
+  impl Context for Items {
+    fn get(&self, _: &str) -> Option<&dyn Context> {
+      None
+    }
+
+    fn is_truthy(&self) -> bool {
+      !self.0.is_empty()
+    }
+
+    fn items(&self) -> Option<Box<dyn Iterator<Item = &dyn Context> + '_>> {
+      Some(Box::new(self.0.iter().map(|item| item as &dyn Context)))
+    }
+
+    fn text(&self) -> Option<Cow<'_, str>> {
+      None
+    }
+  }
+
+  impl Context for Item {
+    fn get(&self, name: &str) -> Option<&dyn Context> {
+      match name {
+        "wrap" => Some(&self.0),
+        _ => None,
+      }
+    }
+
+    fn is_truthy(&self) -> bool {
+      true
+    }
+
+    fn text(&self) -> Option<Cow<'_, str>> {
+      None
+    }
+  }
+
+  #[test]
+  fn list_items_carry_their_own_lambdas() {
+    let items = Items(vec![
+      Item(Lambda::Section(Box::new(|text| format!("<{}>", text)))),
+      Item(Lambda::Section(Box::new(|text| format!("[{}]", text)))),
+    ]);
+    let template = Template::compile("{{#.}}{{#wrap}}x{{/wrap}}{{/.}}").unwrap();
+    assert_eq!("<x>[x]", template.render_with(&items, |_: &str| None, &Options::new()).unwrap().output);
+  }
+}
@@ -1,16 +1,444 @@
+mod check;
+mod context;
 mod emitter;
+mod escape;
+mod format;
+mod javascript;
+mod lambda;
+mod loader;
+mod options;
+mod owned;
 mod parser;
+mod references;
 mod resolver;
+mod schema;
 mod types;
 
+use std::{fmt, io};
+
+use emitter::{FmtOutput, IoOutput};
+
+pub use context::{Context, Converted};
+pub use escape::{Csv, Escaper, Escapers, Html, Json, Latex, NoEscape, Shell};
+pub use format::{Formatter, Spacing};
+pub use lambda::{Lambda, Lambdas};
+pub use loader::{ChainLoader, DirectoryLoader, HashMapLoader, PartialLoader};
+pub use options::{Missing, Options};
+pub use parser::{Cst, CstNode, CstSection, CstTag, CstTagKind, CstToken, IncrementalParse};
+pub use owned::{
+  OwnedBlockTag, OwnedContextTag, OwnedDelimiterTag, OwnedKeyTag, OwnedParentTag, OwnedPartialTag,
+  OwnedTag, OwnedTemplate, OwnedValueTag, FORMAT_VERSION,
+};
+pub use references::{Reference, ReferenceKind, References, Scope};
+pub use resolver::lookup;
 pub use types::*;
 
-pub fn render<P>(text: &str, context: &Value, partials: P) -> Result<String>
+impl<'a> Template<'a> {
+  /// Parses template text once so it can be rendered any number of times.
+  pub fn compile(text: &'a str) -> Result<Template<'a>> {
+    let (template, _) = parser::template(text)?;
+    Ok(template)
+  }
+
+  /// Escapes `{{name}}` tags with `escaper` whatever the render options say,
+  /// for a template that only makes sense in one format.
+  pub fn escaper(mut self, escaper: &'a dyn Escaper) -> Template<'a> {
+    self.escaper = Some(escaper);
+    self
+  }
+
+  /// Renders with partials from a `PartialLoader`: a directory, a map, or any
+  /// `Fn(&str) -> Option<String>`.
+  pub fn render<C, L>(&self, context: &C, partials: L) -> Result<String>
+  where
+    C: Context + ?Sized,
+    L: PartialLoader,
+  {
+    Ok(self.render_with(context, partials, &Options::new())?.output)
+  }
+
+  pub fn render_with<C, L>(&self, context: &C, partials: L, options: &Options) -> Result<Rendered>
+  where
+    C: Context + ?Sized,
+    L: PartialLoader,
+  {
+    let mut output = String::new();
+    let warnings = emitter::emit(FmtOutput(&mut output), self, &context, partials, options)?;
+    Ok(Rendered { output, warnings })
+  }
+
+  /// Streams the output into a writer as it renders, without collecting it.
+  pub fn render_to<W, C, L>(&self, writer: W, context: &C, partials: L) -> Result<()>
+  where
+    W: io::Write,
+    C: Context + ?Sized,
+    L: PartialLoader,
+  {
+    self.render_to_with(writer, context, partials, &Options::new())?;
+    Ok(())
+  }
+
+  pub fn render_to_with<W, C, L>(&self, writer: W, context: &C, partials: L, options: &Options) -> Result<Vec<Warning>>
+  where
+    W: io::Write,
+    C: Context + ?Sized,
+    L: PartialLoader,
+  {
+    emitter::emit(IoOutput(writer), self, &context, partials, options)
+  }
+
+  /// Streams the output into a formatter or string as it renders.
+  pub fn render_fmt<W, C, L>(&self, writer: W, context: &C, partials: L) -> Result<()>
+  where
+    W: fmt::Write,
+    C: Context + ?Sized,
+    L: PartialLoader,
+  {
+    self.render_fmt_with(writer, context, partials, &Options::new())?;
+    Ok(())
+  }
+
+  pub fn render_fmt_with<W, C, L>(&self, writer: W, context: &C, partials: L, options: &Options) -> Result<Vec<Warning>>
+  where
+    W: fmt::Write,
+    C: Context + ?Sized,
+    L: PartialLoader,
+  {
+    emitter::emit(FmtOutput(writer), self, &context, partials, options)
+  }
+}
+
+pub fn render<C, L>(text: &str, context: &C, partials: L) -> Result<String>
+where
+  C: Context + ?Sized,
+  L: PartialLoader,
+{
+  Template::compile(text)?.render(context, partials)
+}
+
+pub fn render_with<C, L>(text: &str, context: &C, partials: L, options: &Options) -> Result<Rendered>
+where
+  C: Context + ?Sized,
+  L: PartialLoader,
+{
+  Template::compile(text)?.render_with(context, partials, options)
+}
+
+pub fn render_to<W, C, L>(writer: W, text: &str, context: &C, partials: L) -> Result<()>
 where
-  P: Fn(&str) -> Option<String>,
+  W: io::Write,
+  C: Context + ?Sized,
+  L: PartialLoader,
 {
-  let (template, _) = parser::template(text)?;
-  let mut bytes: Vec<u8> = vec![];
-  emitter::emit(&mut bytes, &template, &context, partials).map_err(|err| format!("{}", err))?;
-  String::from_utf8(bytes).map_err(|err| format!("{}", err))
+  Template::compile(text)?.render_to(writer, context, partials)
+}
+
+pub fn render_fmt<W, C, L>(writer: W, text: &str, context: &C, partials: L) -> Result<()>
+where
+  W: fmt::Write,
+  C: Context + ?Sized,
+  L: PartialLoader,
+{
+  Template::compile(text)?.render_fmt(writer, context, partials)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+  use std::cell::Cell;
+
+  #[test]
+  fn compile_once_render_many() -> Result<()> {
+    let template = Template::compile("{{#items}}<{{>item}}>{{/items}}")?;
+    let partials = |name: &str| match name {
+      "item" => Some("{{name}}".to_string()),
+      _ => None,
+    };
+
+    let first = template.render(&json!({ "items": [{ "name": "a" }] }), partials)?;
+    let second = template.render(&json!({ "items": [{ "name": "b" }, { "name": "c" }] }), partials)?;
+
+    assert_eq!("<a>", first);
+    assert_eq!("<b><c>", second);
+    Ok(())
+  }
+
+  #[test]
+  fn render_into_writers() -> Result<()> {
+    let template = Template::compile("{{#items}}<{{.}}>{{/items}}")?;
+    let context = json!({ "items": ["a", "é"] });
+    let partials = |_: &str| None;
+
+    let mut bytes: Vec<u8> = vec![];
+    template.render_to(&mut bytes, &context, partials)?;
+    let mut text = String::from("items: ");
+    template.render_fmt(&mut text, &context, partials)?;
+
+    assert_eq!("<a><é>".as_bytes(), bytes.as_slice());
+    assert_eq!("items: <a><é>", text);
+    Ok(())
+  }
+
+  #[test]
+  fn writer_errors_are_reported() {
+    struct Full;
+    impl io::Write for Full {
+      fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::WriteZero, "full"))
+      }
+      fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+      }
+    }
+
+    let result = render_to(Full, "alpha", &json!({}), |_: &str| None);
+
+    assert!(matches!(result, Err(Error::Write(err)) if err.kind() == io::ErrorKind::WriteZero));
+  }
+
+  #[test]
+  fn partials_load_once_per_render() -> Result<()> {
+    let loads = Cell::new(0);
+    let partials = |name: &str| {
+      loads.set(loads.get() + 1);
+      match name {
+        "row" => Some("[{{.}}]".to_string()),
+        _ => None,
+      }
+    };
+
+    let output = render("{{#rows}}{{>row}}{{>gap}}{{/rows}}", &json!({ "rows": [1, 2, 3] }), partials)?;
+
+    assert_eq!("[1][2][3]", output);
+    assert_eq!(2, loads.get());
+    Ok(())
+  }
+
+  #[test]
+  fn nested_partials_compound_indentation() -> Result<()> {
+    let partials = |name: &str| match name {
+      "outer" => Some("outer:\n  {{>inner}}\n".to_string()),
+      "inner" => Some("inner: {{value}}\n".to_string()),
+      _ => None,
+    };
+
+    let output = render("root:\n  {{>outer}}\n", &json!({ "value": 1 }), partials)?;
+
+    assert_eq!("root:\n  outer:\n    inner: 1\n", output);
+    Ok(())
+  }
+
+  #[test]
+  fn dynamic_partial_names() -> Result<()> {
+    let partials = |name: &str| match name {
+      "video" => Some("[video {{title}}]".to_string()),
+      "image" => Some("[image {{title}}]".to_string()),
+      _ => None,
+    };
+    let context = json!({
+      "cards": [
+        { "type": "video", "title": "a" },
+        { "type": "image", "title": "b" },
+        { "type": "audio", "title": "c" },
+        { "title": "d" },
+      ]
+    });
+
+    let output = render("{{#cards}}{{>*type}}{{/cards}}", &context, partials)?;
+
+    assert_eq!("[video a][image b]", output);
+    Ok(())
+  }
+
+  #[test]
+  fn render_serialized_data() -> Result<()> {
+    #[derive(serde::Serialize)]
+    struct Line {
+      item: &'static str,
+      count: u32,
+    }
+    #[derive(serde::Serialize)]
+    struct Order {
+      id: u32,
+      lines: Vec<Line>,
+    }
+    let order = Order {
+      id: 7,
+      lines: vec![Line { item: "pen", count: 2 }, Line { item: "ink", count: 1 }],
+    };
+
+    let output = render("#{{id}}:{{#lines}} {{count}}x{{item}}{{/lines}}", &Converted::new(&order)?, |_: &str| None)?;
+
+    assert_eq!("#7: 2xpen 1xink", output);
+    Ok(())
+  }
+
+  #[test]
+  fn strict_renders_fail_on_missing_keys() {
+    let options = Options::new().missing(Missing::Fail);
+    let context = json!({ "user": { "name": "Ada" } });
+
+    match render_with("Hi {{user.name}},\n{{#user}}{{nmae}}{{/user}}", &context, |_: &str| None, &options) {
+      Err(Error::MissingKey { position, key }) => {
+        assert_eq!(Position { offset: 27, line: 2, column: 10 }, position);
+        assert_eq!("nmae", key);
+      }
+      other => panic!("expected a missing key, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn missing_keys_in_partials_name_the_partial() {
+    let options = Options::new().missing(Missing::Fail);
+    let partials = |_: &str| Some("{{absent}}".to_string());
+
+    match render_with("{{>card}}", &json!({}), partials, &options) {
+      Err(Error::Partial { name, error, .. }) => {
+        assert_eq!("card", name);
+        assert!(matches!(*error, Error::MissingKey { ref key, .. } if key == "absent"));
+      }
+      other => panic!("expected a partial error, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn warnings_collect_every_missing_key() -> Result<()> {
+    let options = Options::new().missing(Missing::Warn);
+    let partials = |_: &str| Some("{{{title}}}".to_string());
+    let context = json!({ "name": "Ada", "flag": null });
+
+    let rendered = render_with("{{name}}{{nmae}}{{#items}}{{/items}}{{^flag}}{{>card}}{{/flag}}", &context, partials, &options)?;
+
+    assert_eq!("Ada", rendered.output);
+    let keys: Vec<_> = rendered
+      .warnings
+      .iter()
+      .map(|warning| (warning.key.as_str(), warning.partial.as_deref()))
+      .collect();
+    assert_eq!(vec![("nmae", None), ("items", None), ("title", Some("card"))], keys);
+    Ok(())
+  }
+
+  #[test]
+  fn missing_partials_follow_the_options() -> Result<()> {
+    let template = Template::compile("a\n {{>nope}}")?;
+
+    assert_eq!("a\n", template.render(&json!({}), |_: &str| None)?);
+    let rendered = template.render_with(&json!({}), |_: &str| None, &Options::new().missing(Missing::Warn))?;
+    assert_eq!("2:2: partial 'nope' not found", rendered.warnings[0].to_string());
+    assert_eq!(Missed::Partial, rendered.warnings[0].missed);
+    match template.render_with(&json!({}), |_: &str| None, &Options::new().missing(Missing::Fail)) {
+      Err(Error::MissingPartial { position, name }) => {
+        assert_eq!(Position { offset: 3, line: 2, column: 2 }, position);
+        assert_eq!("nope", name);
+      }
+      other => panic!("expected a missing partial, got {:?}", other),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn escapers_per_render() -> Result<()> {
+    let template = Template::compile("{\"title\": \"{{title}}\", \"raw\": \"{{{title}}}\"}")?;
+    let context = json!({ "title": "<\"quoted\">" });
+    let escapers = Escapers::default();
+
+    let html = template.render(&context, |_: &str| None)?;
+    let json = template.render_with(&context, |_: &str| None, &Options::new().escaper(escapers.get("json").unwrap()))?;
+
+    assert_eq!("{\"title\": \"&lt;&quot;quoted&quot;&gt;\", \"raw\": \"<\"quoted\">\"}", html);
+    assert_eq!("{\"title\": \"<\\\"quoted\\\">\", \"raw\": \"<\"quoted\">\"}", json.output);
+    Ok(())
+  }
+
+  #[test]
+  fn escapers_per_template() -> Result<()> {
+    let template = Template::compile("{\"rows\": [{{>row}}]}")?.escaper(&Json);
+    let context = json!({ "name": "<\"a\">" });
+    let partials = |_: &str| Some("\"{{name}}\"".to_string());
+
+    let output = template.render_with(&context, partials, &Options::new().escaper(&Html))?;
+
+    assert_eq!("{\"rows\": [\"<\\\"a\\\">\"]}", output.output);
+    Ok(())
+  }
+
+  #[test]
+  fn loader_errors_are_reported() {
+    struct Offline;
+    impl PartialLoader for Offline {
+      fn load(&self, _: &str) -> io::Result<Option<String>> {
+        Err(io::Error::new(io::ErrorKind::ConnectionRefused, "offline"))
+      }
+    }
+
+    let result = Template::compile("x {{>remote}}").and_then(|template| template.render_with(&json!({}), Offline, &Options::new()));
+
+    assert!(matches!(result, Err(Error::Load { position, name, .. }) if name == "remote" && position.column == 3));
+  }
+
+  #[test]
+  fn runaway_recursion_hits_the_depth_limit() {
+    let partials = |_: &str| Some("{{#.}}{{>loop}}{{/.}}".to_string());
+    let options = Options::new().max_depth(8);
+
+    let result = render_with("{{>loop}}", &json!(true), partials, &options);
+
+    assert!(matches!(result, Err(Error::DepthExceeded { position, name, depth: 8 }) if name == "loop" && position.offset == 0));
+    let result = render("{{>loop}}", &json!(true), partials);
+    assert!(matches!(result, Err(Error::DepthExceeded { depth: 100, .. })));
+  }
+
+  #[test]
+  fn partial_parse_errors_are_reported() {
+    let partials = |_: &str| Some("{{#open}}".to_string());
+
+    match render("{{>broken}}", &json!({}), partials) {
+      Err(Error::Partial { name, error, .. }) => {
+        assert_eq!("broken", name);
+        assert!(matches!(*error, Error::UnclosedSection { .. }));
+      }
+      _ => panic!("expected a partial error"),
+    }
+  }
+
+  #[test]
+  fn errors_in_indented_partials_point_into_the_partial() {
+    let partials = |_: &str| Some("{{x}}".to_string());
+    let options = Options::new().missing(Missing::Fail);
+
+    match render_with("    {{>p}}\n", &json!({}), partials, &options) {
+      Err(Error::Partial { error, .. }) => match *error {
+        Error::MissingKey { position, .. } => assert_eq!((1, 1), (position.line, position.column)),
+        other => panic!("expected a missing key, got {:?}", other),
+      },
+      other => panic!("expected a partial error, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn partials_are_indented_as_they_render() -> Result<()> {
+    let partials = |name: &str| match name {
+      "list" => Some("{{#items}}\n- {{.}}\n{{/items}}\n{{! end }}\n{{>item}}".to_string()),
+      "item" => Some("{{x}}\n".to_string()),
+      _ => None,
+    };
+    let context = json!({ "items": ["a", "b"], "x": "1\n2" });
+
+    assert_eq!("  - a\n  - b\n  1\n2\n", render("  {{>list}}\n", &context, partials)?);
+    Ok(())
+  }
+
+  #[test]
+  fn lines_with_several_tags_do_not_stand_alone() -> Result<()> {
+    let partials = |name: &str| match name {
+      "p" => Some("P\nP2\n".to_string()),
+      "q" => Some("Q\n".to_string()),
+      _ => None,
+    };
+
+    assert_eq!("  P\nP2\nQ\n\n", render("  {{>p}}{{>q}}\n", &json!({}), partials)?);
+    Ok(())
+  }
 }
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::fs;
+
+/// Provides the source of partials by name. A missing partial is `Ok(None)`
+/// and renders as nothing; an error aborts the render with `Error::Load`.
+pub trait PartialLoader {
+  fn load(&self, name: &str) -> io::Result<Option<String>>;
+}
+
+/// A function from names to partial sources, which can't fail.
+impl<F> PartialLoader for F
+where
+  F: Fn(&str) -> Option<String>,
+{
+  fn load(&self, name: &str) -> io::Result<Option<String>> {
+    Ok(self(name))
+  }
+}
+
+/// Loads partials from files under a directory, so `{{>layout/header}}` reads
+/// `layout/header.mustache`. Names leaving the directory are never loaded.
+#[derive(Debug, Clone)]
+pub struct DirectoryLoader {
+  root: PathBuf,
+  extensions: Vec<String>,
+}
+
+impl DirectoryLoader {
+  pub fn new<P: AsRef<Path>>(root: P) -> DirectoryLoader {
+    DirectoryLoader {
+      root: root.as_ref().to_path_buf(),
+      extensions: vec!["mustache".to_string()],
+    }
+  }
+
+  /// Replaces the file extensions tried for each name, in order. An empty
+  /// extension matches the bare name.
+  pub fn extensions(mut self, extensions: &[&str]) -> DirectoryLoader {
+    self.extensions = extensions.iter().map(|extension| extension.to_string()).collect();
+    self
+  }
+}
+
+impl PartialLoader for DirectoryLoader {
+  fn load(&self, name: &str) -> io::Result<Option<String>> {
+    let relative = Path::new(name);
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+      return Ok(None);
+    }
+    for extension in self.extensions.iter() {
+      let mut path = self.root.join(relative).into_os_string();
+      if !extension.is_empty() {
+        path.push(".");
+        path.push(extension);
+      }
+      match fs::read_to_string(&path) {
+        Ok(source) => return Ok(Some(source)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+        Err(err) => return Err(err),
+      }
+    }
+    Ok(None)
+  }
+}
+
+/// Partials held in memory, by name.
+#[derive(Debug, Clone, Default)]
+pub struct HashMapLoader {
+  partials: HashMap<String, String>,
+}
+
+impl HashMapLoader {
+  pub fn new() -> HashMapLoader {
+    HashMapLoader::default()
+  }
+
+  pub fn partial(mut self, name: &str, source: &str) -> HashMapLoader {
+    self.partials.insert(name.to_string(), source.to_string());
+    self
+  }
+}
+
+impl From<HashMap<String, String>> for HashMapLoader {
+  fn from(partials: HashMap<String, String>) -> HashMapLoader {
+    HashMapLoader { partials }
+  }
+}
+
+impl PartialLoader for HashMapLoader {
+  fn load(&self, name: &str) -> io::Result<Option<String>> {
+    Ok(self.partials.get(name).cloned())
+  }
+}
+
+/// Tries several loaders in order, taking the first partial found.
+#[derive(Default)]
+pub struct ChainLoader<'l> {
+  loaders: Vec<Box<dyn PartialLoader + 'l>>,
+}
+
+impl<'l> ChainLoader<'l> {
+  pub fn new() -> ChainLoader<'l> {
+    ChainLoader::default()
+  }
+
+  pub fn then<L>(mut self, loader: L) -> ChainLoader<'l>
+  where
+    L: PartialLoader + 'l,
+  {
+    self.loaders.push(Box::new(loader));
+    self
+  }
+}
+
+impl<'l> PartialLoader for ChainLoader<'l> {
+  fn load(&self, name: &str) -> io::Result<Option<String>> {
+    for loader in self.loaders.iter() {
+      if let Some(source) = loader.load(name)? {
+        return Ok(Some(source));
+      }
+    }
+    Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+
+  fn scratch(name: &str) -> io::Result<PathBuf> {
+    let root = env::temp_dir().join(format!("mustache-loader-{}-{}", name, std::process::id()));
+    fs::create_dir_all(root.join("layout"))?;
+    fs::write(root.join("layout/header.mustache"), "<h1>{{title}}</h1>")?;
+    fs::write(root.join("footer.html"), "<footer/>")?;
+    Ok(root)
+  }
+
+  #[test]
+  fn directory_loader_nested_names() -> io::Result<()> {
+    let root = scratch("nested")?;
+    let loader = DirectoryLoader::new(&root);
+
+    assert_eq!(Some("<h1>{{title}}</h1>".to_string()), loader.load("layout/header")?);
+    assert_eq!(None, loader.load("footer")?);
+    assert_eq!(None, loader.load("../escape")?);
+    assert_eq!(None, loader.load("/etc/passwd")?);
+    fs::remove_dir_all(root)?;
+    Ok(())
+  }
+
+  #[test]
+  fn directory_loader_extensions() -> io::Result<()> {
+    let root = scratch("extensions")?;
+    let loader = DirectoryLoader::new(&root).extensions(&["mustache", "html"]);
+
+    assert_eq!(Some("<footer/>".to_string()), loader.load("footer")?);
+    fs::remove_dir_all(root)?;
+    Ok(())
+  }
+
+  #[test]
+  fn chain_loader_takes_the_first_hit() -> io::Result<()> {
+    let overrides = HashMapLoader::new().partial("header", "custom");
+    let defaults = HashMapLoader::new().partial("header", "default").partial("footer", "default");
+    let loader = ChainLoader::new()
+      .then(overrides)
+      .then(defaults)
+      .then(|name: &str| Some(format!("generated {}", name)));
+
+    assert_eq!(Some("custom".to_string()), loader.load("header")?);
+    assert_eq!(Some("default".to_string()), loader.load("footer")?);
+    assert_eq!(Some("generated aside".to_string()), loader.load("aside")?);
+    Ok(())
+  }
+}
@@ -0,0 +1,66 @@
+use super::escape::{Escaper, Html};
+use super::lambda::Lambdas;
+
+/// How a render treats a key, or a partial, that resolves to nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Missing {
+  /// Render nothing in its place, as the spec asks.
+  Ignore,
+  /// Render nothing in its place, and report it next to the output.
+  Warn,
+  /// Abort the render with `Error::MissingKey` or `Error::MissingPartial`.
+  Fail,
+}
+
+/// Settings for a single render, built up from `Options::new()`.
+pub struct Options<'o> {
+  pub(crate) lambdas: Option<&'o Lambdas>,
+  pub(crate) missing: Missing,
+  pub(crate) escaper: &'o dyn Escaper,
+  pub(crate) max_depth: usize,
+}
+
+impl<'o> Default for Options<'o> {
+  fn default() -> Options<'o> {
+    Options {
+      lambdas: None,
+      missing: Missing::Ignore,
+      escaper: &Html,
+      max_depth: 100,
+    }
+  }
+}
+
+impl<'o> Options<'o> {
+  pub fn new() -> Options<'o> {
+    Options::default()
+  }
+
+  pub fn lambdas(mut self, lambdas: &'o Lambdas) -> Options<'o> {
+    self.lambdas = Some(lambdas);
+    self
+  }
+
+  /// Replaces HTML escaping of `{{name}}` tags, for templates producing some
+  /// other format. A template's own escaper still wins.
+  pub fn escaper(mut self, escaper: &'o dyn Escaper) -> Options<'o> {
+    self.escaper = escaper;
+    self
+  }
+
+  /// Limits how deeply partials and parents may nest before the render fails
+  /// with `Error::DepthExceeded`, so runaway recursion cannot overflow the
+  /// stack. Defaults to 100.
+  pub fn max_depth(mut self, max_depth: usize) -> Options<'o> {
+    self.max_depth = max_depth;
+    self
+  }
+
+  /// Decides what happens to keys in variables, sections and dynamic partial
+  /// names that resolve to nothing, and to partials the loader does not have.
+  /// Inverted sections exist to handle missing keys, so they never count.
+  pub fn missing(mut self, missing: Missing) -> Options<'o> {
+    self.missing = missing;
+    self
+  }
+}
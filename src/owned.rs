@@ -0,0 +1,388 @@
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use super::types::{
+  BlockTag, ContextTag, DelimiterTag, Error, KeyTag, ParentTag, PartialTag, Result, Span, Tag,
+  Template, ValueTag,
+};
+
+/// The version of the encoding written by `OwnedTemplate`. Bumped whenever the
+/// shape of the tree changes, so stale precompiled templates are refused.
+pub const FORMAT_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedContextTag {
+  pub key: Vec<String>,
+  pub tags: Vec<OwnedTag>,
+  pub source: String,
+  pub delimiters: OwnedDelimiterTag,
+  pub span: Span,
+  pub close: Span,
+  pub standalone: bool,
+  pub close_standalone: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedKeyTag {
+  pub key: Vec<String>,
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedValueTag {
+  pub value: String,
+  pub span: Span,
+  pub standalone: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedPartialTag {
+  pub name: String,
+  pub key: Option<Vec<String>>,
+  pub indentation: String,
+  pub span: Span,
+  pub standalone: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedParentTag {
+  pub name: String,
+  pub indentation: String,
+  pub tags: Vec<OwnedTag>,
+  pub span: Span,
+  pub close: Span,
+  pub standalone: bool,
+  pub close_standalone: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedBlockTag {
+  pub name: String,
+  pub tags: Vec<OwnedTag>,
+  pub span: Span,
+  pub close: Span,
+  pub standalone: bool,
+  pub close_standalone: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedDelimiterTag {
+  pub start: String,
+  pub stop: String,
+  pub span: Span,
+  pub standalone: bool,
+}
+
+/// A `Tag` that owns its text rather than borrowing it from the source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OwnedTag {
+  Text(OwnedValueTag),
+  Variable(OwnedKeyTag),
+  Unescaped(OwnedKeyTag),
+  Inverted(OwnedContextTag),
+  Section(OwnedContextTag),
+  Comment(OwnedValueTag),
+  Partial(OwnedPartialTag),
+  Parent(OwnedParentTag),
+  Block(OwnedBlockTag),
+  Delimiters(OwnedDelimiterTag),
+}
+
+/// A parsed template that owns its text, so it can be stored, shipped and
+/// loaded again without the source or a second parse.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedTemplate {
+  pub version: u32,
+  pub tags: Vec<OwnedTag>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+  version: u32,
+  #[serde(rename = "tags")]
+  _tags: IgnoredAny,
+}
+
+fn format_error<E: std::fmt::Display>(err: E) -> Error {
+  Error::Format {
+    message: err.to_string(),
+  }
+}
+
+fn check_version(version: u32) -> Result<()> {
+  if version == FORMAT_VERSION {
+    Ok(())
+  } else {
+    Err(Error::FormatVersion { version })
+  }
+}
+
+fn key_to_owned(key: &[&str]) -> Vec<String> {
+  key.iter().map(|name| name.to_string()).collect()
+}
+
+fn key_to_borrowed(key: &[String]) -> Vec<&str> {
+  key.iter().map(String::as_str).collect()
+}
+
+fn tags_to_owned(tags: &[Tag]) -> Vec<OwnedTag> {
+  tags.iter().map(OwnedTag::from).collect()
+}
+
+fn tags_to_borrowed(tags: &[OwnedTag]) -> Vec<Tag<'_>> {
+  tags.iter().map(OwnedTag::tag).collect()
+}
+
+impl OwnedDelimiterTag {
+  fn from(tag: &DelimiterTag) -> OwnedDelimiterTag {
+    OwnedDelimiterTag {
+      start: tag.start.to_string(),
+      stop: tag.stop.to_string(),
+      span: tag.span,
+      standalone: tag.standalone,
+    }
+  }
+
+  fn tag(&self) -> DelimiterTag<'_> {
+    DelimiterTag {
+      start: &self.start,
+      stop: &self.stop,
+      span: self.span,
+      standalone: self.standalone,
+    }
+  }
+}
+
+impl OwnedContextTag {
+  fn from(tag: &ContextTag) -> OwnedContextTag {
+    OwnedContextTag {
+      key: key_to_owned(&tag.key),
+      tags: tags_to_owned(&tag.tags),
+      source: tag.source.to_string(),
+      delimiters: OwnedDelimiterTag::from(&tag.delimiters),
+      span: tag.span,
+      close: tag.close,
+      standalone: tag.standalone,
+      close_standalone: tag.close_standalone,
+    }
+  }
+
+  fn tag(&self) -> ContextTag<'_> {
+    ContextTag {
+      key: key_to_borrowed(&self.key),
+      tags: tags_to_borrowed(&self.tags),
+      source: &self.source,
+      delimiters: self.delimiters.tag(),
+      span: self.span,
+      close: self.close,
+      standalone: self.standalone,
+      close_standalone: self.close_standalone,
+    }
+  }
+}
+
+impl OwnedTag {
+  pub(crate) fn from(tag: &Tag) -> OwnedTag {
+    match tag {
+      Tag::Text(text) => OwnedTag::Text(OwnedValueTag {
+        value: text.value.to_string(),
+        span: text.span,
+        standalone: text.standalone,
+      }),
+      Tag::Variable(variable) => OwnedTag::Variable(OwnedKeyTag {
+        key: key_to_owned(&variable.key),
+        span: variable.span,
+      }),
+      Tag::Unescaped(variable) => OwnedTag::Unescaped(OwnedKeyTag {
+        key: key_to_owned(&variable.key),
+        span: variable.span,
+      }),
+      Tag::Inverted(section) => OwnedTag::Inverted(OwnedContextTag::from(section)),
+      Tag::Section(section) => OwnedTag::Section(OwnedContextTag::from(section)),
+      Tag::Comment(comment) => OwnedTag::Comment(OwnedValueTag {
+        value: comment.value.to_string(),
+        span: comment.span,
+        standalone: comment.standalone,
+      }),
+      Tag::Partial(partial) => OwnedTag::Partial(OwnedPartialTag {
+        name: partial.name.to_string(),
+        key: partial.key.as_deref().map(key_to_owned),
+        indentation: partial.indentation.to_string(),
+        span: partial.span,
+        standalone: partial.standalone,
+      }),
+      Tag::Parent(parent) => OwnedTag::Parent(OwnedParentTag {
+        name: parent.name.to_string(),
+        indentation: parent.indentation.to_string(),
+        tags: tags_to_owned(&parent.tags),
+        span: parent.span,
+        close: parent.close,
+        standalone: parent.standalone,
+        close_standalone: parent.close_standalone,
+      }),
+      Tag::Block(block) => OwnedTag::Block(OwnedBlockTag {
+        name: block.name.to_string(),
+        tags: tags_to_owned(&block.tags),
+        span: block.span,
+        close: block.close,
+        standalone: block.standalone,
+        close_standalone: block.close_standalone,
+      }),
+      Tag::Delimiters(delimiters) => OwnedTag::Delimiters(OwnedDelimiterTag::from(delimiters)),
+    }
+  }
+
+  /// A borrowed view of this tag, as the emitter renders it.
+  pub fn tag(&self) -> Tag<'_> {
+    match self {
+      OwnedTag::Text(text) => Tag::Text(ValueTag {
+        value: &text.value,
+        span: text.span,
+        standalone: text.standalone,
+      }),
+      OwnedTag::Variable(variable) => Tag::Variable(KeyTag {
+        key: key_to_borrowed(&variable.key),
+        span: variable.span,
+      }),
+      OwnedTag::Unescaped(variable) => Tag::Unescaped(KeyTag {
+        key: key_to_borrowed(&variable.key),
+        span: variable.span,
+      }),
+      OwnedTag::Inverted(section) => Tag::Inverted(section.tag()),
+      OwnedTag::Section(section) => Tag::Section(section.tag()),
+      OwnedTag::Comment(comment) => Tag::Comment(ValueTag {
+        value: &comment.value,
+        span: comment.span,
+        standalone: comment.standalone,
+      }),
+      OwnedTag::Partial(partial) => Tag::Partial(PartialTag {
+        name: &partial.name,
+        key: partial.key.as_deref().map(key_to_borrowed),
+        indentation: &partial.indentation,
+        span: partial.span,
+        standalone: partial.standalone,
+      }),
+      OwnedTag::Parent(parent) => Tag::Parent(ParentTag {
+        name: &parent.name,
+        indentation: &parent.indentation,
+        tags: tags_to_borrowed(&parent.tags),
+        span: parent.span,
+        close: parent.close,
+        standalone: parent.standalone,
+        close_standalone: parent.close_standalone,
+      }),
+      OwnedTag::Block(block) => Tag::Block(BlockTag {
+        name: &block.name,
+        tags: tags_to_borrowed(&block.tags),
+        span: block.span,
+        close: block.close,
+        standalone: block.standalone,
+        close_standalone: block.close_standalone,
+      }),
+      OwnedTag::Delimiters(delimiters) => Tag::Delimiters(delimiters.tag()),
+    }
+  }
+}
+
+impl OwnedTemplate {
+  /// A borrowed view of this template, ready to render.
+  pub fn template(&self) -> Template<'_> {
+    Template {
+      tags: tags_to_borrowed(&self.tags),
+      escaper: None,
+    }
+  }
+
+  pub fn to_json(&self) -> Result<String> {
+    serde_json::to_string(self).map_err(format_error)
+  }
+
+  pub fn from_json(json: &str) -> Result<OwnedTemplate> {
+    let header: Header = serde_json::from_str(json).map_err(format_error)?;
+    check_version(header.version)?;
+    serde_json::from_str(json).map_err(format_error)
+  }
+
+  /// Encodes the template as MessagePack: the format version, then the tags.
+  pub fn to_bytes(&self) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    rmp_serde::encode::write(&mut bytes, &self.version).map_err(format_error)?;
+    rmp_serde::encode::write(&mut bytes, &self.tags).map_err(format_error)?;
+    Ok(bytes)
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<OwnedTemplate> {
+    let mut deserializer = rmp_serde::Deserializer::new(bytes);
+    let version = u32::deserialize(&mut deserializer).map_err(format_error)?;
+    check_version(version)?;
+    let tags = Vec::deserialize(&mut deserializer).map_err(format_error)?;
+    Ok(OwnedTemplate { version, tags })
+  }
+}
+
+impl<'a> Template<'a> {
+  /// Copies the template out of its source, for serializing.
+  pub fn to_owned_template(&self) -> OwnedTemplate {
+    OwnedTemplate {
+      version: FORMAT_VERSION,
+      tags: tags_to_owned(&self.tags),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser;
+
+  const SOURCE: &str = "{{! note }}\n{{=<% %>=}}<%#items%>\n  <%> row %>\n<%/items%><%^none%>-<%/none%>\
+    <%<layout%><%$body%><%&html%><%/body%><%/layout%><%>*kind%>{{plain}}";
+
+  fn owned() -> OwnedTemplate {
+    let (template, _) = parser::template(SOURCE).unwrap();
+    template.to_owned_template()
+  }
+
+  #[test]
+  fn json_round_trip() -> Result<()> {
+    let template = owned();
+
+    let json = template.to_json()?;
+
+    assert!(json.starts_with("{\"version\":3,"));
+    assert_eq!(template, OwnedTemplate::from_json(&json)?);
+    Ok(())
+  }
+
+  #[test]
+  fn binary_round_trip() -> Result<()> {
+    let template = owned();
+
+    let bytes = template.to_bytes()?;
+
+    assert!(bytes.len() < template.to_json()?.len());
+    assert_eq!(template, OwnedTemplate::from_bytes(&bytes)?);
+    Ok(())
+  }
+
+  #[test]
+  fn borrowed_view_round_trip() {
+    let template = owned();
+
+    assert_eq!(template, template.template().to_owned_template());
+  }
+
+  #[test]
+  fn other_versions_are_refused() -> Result<()> {
+    let mut template = owned();
+    template.version = FORMAT_VERSION + 1;
+
+    let json = serde_json::to_string(&template).map_err(format_error)?;
+    let mut bytes = vec![];
+    rmp_serde::encode::write(&mut bytes, &template.version).map_err(format_error)?;
+
+    assert!(matches!(OwnedTemplate::from_json(&json), Err(Error::FormatVersion { version: 4 })));
+    assert!(matches!(OwnedTemplate::from_bytes(&bytes), Err(Error::FormatVersion { version: 4 })));
+    Ok(())
+  }
+}
@@ -1,14 +1,19 @@
-use crate::types::Result;
+/// A parser that did not match its input. Callers know what was expected
+/// and where, so they turn misses into positioned errors.
+#[derive(Debug)]
+pub struct Miss;
+
+pub type Result<T> = std::result::Result<T, Miss>;
 
 pub fn take(text: &str, n: usize) -> Result<(&str, &str)> {
   Ok((&text[0..n], &text[n..]))
 }
 
-pub fn string<'a, 'b>(text: &'a str, value: &'b str) -> Result<(&'a str, &'a str)> {
+pub fn string<'a>(text: &'a str, value: &str) -> Result<(&'a str, &'a str)> {
   if text.starts_with(value) {
     take(text, value.len())
   } else {
-    Err(format!("'{}' string not found", value))
+    Err(Miss)
   }
 }
 
@@ -42,7 +47,7 @@ where
 {
   let (value, text) = many_chars(text, pred)?;
   if value.is_empty() {
-    Err("No chars found with the predicate".to_string())
+    Err(Miss)
   } else {
     Ok((value, text))
   }
@@ -66,7 +71,7 @@ where
     }
     return Ok((items, needle));
   }
-  Err("Seperated content not found ".to_string())
+  Err(Miss)
 }
 
 #[cfg(test)]
@@ -0,0 +1,419 @@
+use std::{fmt, mem};
+
+use super::tag::{self, Action, Locator, Token};
+use crate::types::{Error, Result, Span, Template};
+
+/// A piece of source, exactly as written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CstToken<'a> {
+  pub text: &'a str,
+  pub span: Span,
+}
+
+/// Which tag a `CstTag` is, telling `{{{name}}}` and `{{&name}}` apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CstTagKind {
+  Variable,
+  Triple,
+  Ampersand,
+  Section,
+  Inverted,
+  Parent,
+  Block,
+  Close,
+  Comment,
+  Partial,
+  Delimiters,
+}
+
+/// A tag split into its pieces, which join back into the tag's source.
+/// Pieces a tag does not have are empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstTag<'a> {
+  pub kind: CstTagKind,
+  /// The start delimiter, such as `{{`.
+  pub start: CstToken<'a>,
+  /// The character marking the kind of tag, such as `#`.
+  pub sigil: CstToken<'a>,
+  pub leading: CstToken<'a>,
+  /// The key, name, comment or new delimiters, without surrounding space.
+  pub content: CstToken<'a>,
+  pub trailing: CstToken<'a>,
+  /// The `}` of `{{{name}}}` or the `=` of `{{=<% %>=}}`.
+  pub closer: CstToken<'a>,
+  /// The stop delimiter, such as `}}`.
+  pub stop: CstToken<'a>,
+  pub span: Span,
+}
+
+/// A section, inverted section, parent or block, with its closing tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstSection<'a> {
+  pub open: CstTag<'a>,
+  pub children: Vec<CstNode<'a>>,
+  pub close: CstTag<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CstNode<'a> {
+  /// Text, including the whitespace the parser strips around standalone tags.
+  Text(CstToken<'a>),
+  Tag(Box<CstTag<'a>>),
+  Section(Box<CstSection<'a>>),
+}
+
+/// A concrete syntax tree that keeps every byte of the source, for tools that
+/// edit templates without disturbing their formatting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cst<'a> {
+  pub source: &'a str,
+  pub nodes: Vec<CstNode<'a>>,
+}
+
+struct Builder<'a> {
+  source: &'a str,
+  locator: Locator<'a>,
+}
+
+impl<'a> Builder<'a> {
+  fn token(&mut self, start: usize, end: usize) -> CstToken<'a> {
+    CstToken {
+      text: &self.source[start..end],
+      span: self.locator.span(start, end),
+    }
+  }
+
+  fn tag(&mut self, offset: usize, end: usize, start: &str, stop: &str) -> CstTag<'a> {
+    let body_start = offset + start.len();
+    let body_end = end - stop.len();
+    let body = &self.source[body_start..body_end];
+    let (kind, sigil) = match body.chars().next() {
+      Some('{') => (CstTagKind::Triple, 1),
+      Some('&') => (CstTagKind::Ampersand, 1),
+      Some('#') => (CstTagKind::Section, 1),
+      Some('^') => (CstTagKind::Inverted, 1),
+      Some('<') => (CstTagKind::Parent, 1),
+      Some('$') => (CstTagKind::Block, 1),
+      Some('/') => (CstTagKind::Close, 1),
+      Some('!') => (CstTagKind::Comment, 1),
+      Some('>') => (CstTagKind::Partial, 1),
+      Some('=') => (CstTagKind::Delimiters, 1),
+      _ => (CstTagKind::Variable, 0),
+    };
+    let closer = match kind {
+      CstTagKind::Triple | CstTagKind::Delimiters => 1,
+      _ => 0,
+    };
+    let middle = &body[sigil..body.len() - closer];
+    let content = middle.trim_start();
+    let content_start = body_start + sigil + middle.len() - content.len();
+    let content_end = content_start + content.trim_end().len();
+    let closer_start = body_end - closer;
+
+    let start = self.token(offset, body_start);
+    let sigil = self.token(body_start, body_start + sigil);
+    let leading = self.token(sigil.span.end.offset, content_start);
+    let content = self.token(content_start, content_end);
+    let trailing = self.token(content_end, closer_start);
+    let closer = self.token(closer_start, body_end);
+    let stop = self.token(body_end, end);
+    CstTag {
+      kind,
+      span: start.span.to(stop.span),
+      start,
+      sigil,
+      leading,
+      content,
+      trailing,
+      closer,
+      stop,
+    }
+  }
+}
+
+fn flatten<'a>(source: &'a str, nodes: &[CstNode<'a>], tokens: &mut Vec<Token<'a>>) -> Result<()> {
+  for node in nodes {
+    match node {
+      CstNode::Text(text) => tokens.push(Token {
+        action: Action::PushText,
+        start: text.span.start.offset,
+        end: text.span.end.offset,
+        standalone: false,
+      }),
+      CstNode::Tag(tag) => tokens.push(reparse(source, tag)?),
+      CstNode::Section(section) => {
+        tokens.push(reparse(source, &section.open)?);
+        flatten(source, &section.children, tokens)?;
+        tokens.push(reparse(source, &section.close)?);
+      }
+    }
+  }
+  Ok(())
+}
+
+fn reparse<'a>(source: &'a str, tag: &CstTag<'a>) -> Result<Token<'a>> {
+  let (start, end) = (tag.span.start.offset, tag.span.end.offset);
+  let (action, _) = tag::mustache_tag(source, &source[start..], tag.start.text, tag.stop.text)?;
+  Ok(Token {
+    action,
+    start,
+    end,
+    standalone: false,
+  })
+}
+
+impl<'a> Cst<'a> {
+  /// Parses template source, failing where `Template::compile` would.
+  pub fn parse(source: &'a str) -> Result<Cst<'a>> {
+    let mut builder = Builder {
+      source,
+      locator: Locator::new(source),
+    };
+    let (mut start, mut stop) = ("{{", "}}");
+    let mut nodes = vec![];
+    let mut stack: Vec<(CstTag<'a>, &'a str, Vec<CstNode<'a>>)> = vec![];
+
+    for token in tag::tokens(source, start, stop)? {
+      match token.action {
+        Action::PushText => nodes.push(CstNode::Text(builder.token(token.start, token.end))),
+        Action::PushTag { .. } => nodes.push(CstNode::Tag(Box::new(builder.tag(token.start, token.end, start, stop)))),
+        Action::ChangeDelimiter { start: left, stop: right } => {
+          nodes.push(CstNode::Tag(Box::new(builder.tag(token.start, token.end, start, stop))));
+          start = left;
+          stop = right;
+        }
+        Action::PushContext { name, .. } => {
+          let open = builder.tag(token.start, token.end, start, stop);
+          stack.push((open, name, mem::take(&mut nodes)));
+        }
+        Action::PopContext { name } => {
+          let close = builder.tag(token.start, token.end, start, stop);
+          let (open, open_name, parent) = stack.pop().ok_or_else(|| Error::UnopenedClose {
+            position: close.span.start,
+            key: name.to_string(),
+          })?;
+          if open_name != name {
+            return Err(Error::MismatchedClose {
+              position: close.span.start,
+              open: open_name.to_string(),
+              close: name.to_string(),
+            });
+          }
+          let children = mem::replace(&mut nodes, parent);
+          nodes.push(CstNode::Section(Box::new(CstSection { open, children, close })));
+        }
+      }
+    }
+    if let Some((open, name, _)) = stack.pop() {
+      return Err(Error::UnclosedSection {
+        position: open.span.start,
+        key: name.to_string(),
+      });
+    }
+    Ok(Cst { source, nodes })
+  }
+
+  /// Derives the template, as `Template::compile` parses it.
+  pub fn template(&self) -> Result<Template<'a>> {
+    let mut tokens = vec![];
+    flatten(self.source, &self.nodes, &mut tokens)?;
+    tag::build(self.source, tokens, "{{", "}}")
+  }
+
+  /// The delimiters in effect at an offset of template source, as the tags
+  /// before it set them. Only those tags need to be well formed, so this works
+  /// inside a tag that is still being typed, when the source has no `Cst`.
+  /// Panics if the offset is past the end of the source or inside a
+  /// character.
+  pub fn delimiters_at(source: &str, offset: usize) -> (&str, &str) {
+    assert!(
+      source.is_char_boundary(offset),
+      "offset {} is not a character boundary of the source",
+      offset
+    );
+    let source = &source[..offset];
+    let (mut start, mut stop) = ("{{", "}}");
+    let mut needle = source;
+    while !needle.is_empty() {
+      match tag::mustache_tag(source, needle, start, stop) {
+        Ok((Action::ChangeDelimiter { start: left, stop: right }, rest)) => {
+          start = left;
+          stop = right;
+          needle = rest;
+        }
+        Ok((_, rest)) => needle = rest,
+        Err(_) => break,
+      }
+    }
+    (start, stop)
+  }
+
+  /// Every tag in source order, closing tags included.
+  pub fn tags(&self) -> Vec<&CstTag<'a>> {
+    fn collect<'n, 'a>(nodes: &'n [CstNode<'a>], tags: &mut Vec<&'n CstTag<'a>>) {
+      for node in nodes {
+        match node {
+          CstNode::Text(_) => (),
+          CstNode::Tag(tag) => tags.push(tag),
+          CstNode::Section(section) => {
+            tags.push(&section.open);
+            collect(&section.children, tags);
+            tags.push(&section.close);
+          }
+        }
+      }
+    }
+    let mut tags = vec![];
+    collect(&self.nodes, &mut tags);
+    tags
+  }
+}
+
+impl<'a> fmt::Display for CstTag<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for token in [
+      self.start,
+      self.sigil,
+      self.leading,
+      self.content,
+      self.trailing,
+      self.closer,
+      self.stop,
+    ] {
+      f.write_str(token.text)?;
+    }
+    Ok(())
+  }
+}
+
+impl<'a> fmt::Display for CstNode<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CstNode::Text(text) => f.write_str(text.text),
+      CstNode::Tag(tag) => tag.fmt(f),
+      CstNode::Section(section) => {
+        section.open.fmt(f)?;
+        for child in section.children.iter() {
+          child.fmt(f)?;
+        }
+        section.close.fmt(f)
+      }
+    }
+  }
+}
+
+/// Writes the source back out, byte for byte.
+impl<'a> fmt::Display for Cst<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for node in self.nodes.iter() {
+      node.fmt(f)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SOURCE: &str = "{{! note }}\n  {{#items}}\r\n{{{ name }}}{{& name}}{{>* kind }}\n  {{/items}}\n\
+    {{= <% %> =}}<%<page%><%$body%>x<%/body%><%/ page %>";
+
+  #[test]
+  fn keeps_every_byte() -> Result<()> {
+    let cst = Cst::parse(SOURCE)?;
+
+    assert_eq!(SOURCE, cst.to_string());
+    let kinds: Vec<_> = cst.tags().iter().map(|tag| tag.kind).collect();
+    assert_eq!(
+      vec![
+        CstTagKind::Comment,
+        CstTagKind::Section,
+        CstTagKind::Triple,
+        CstTagKind::Ampersand,
+        CstTagKind::Partial,
+        CstTagKind::Close,
+        CstTagKind::Delimiters,
+        CstTagKind::Parent,
+        CstTagKind::Block,
+        CstTagKind::Close,
+        CstTagKind::Close,
+      ],
+      kinds
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn splits_tags_into_pieces() -> Result<()> {
+    let cst = Cst::parse("{{= <% %> =}}<%{ name }%>")?;
+
+    match cst.tags().as_slice() {
+      [delimiters, triple] => {
+        assert_eq!(("{{", "=", " ", "<% %>", " ", "=", "}}"), pieces(delimiters));
+        assert_eq!(("<%", "{", " ", "name", " ", "}", "%>"), pieces(triple));
+        assert_eq!(17..21, triple.content.span.range());
+      }
+      _ => panic!("expected two tags"),
+    }
+    Ok(())
+  }
+
+  fn pieces<'a>(tag: &CstTag<'a>) -> (&'a str, &'a str, &'a str, &'a str, &'a str, &'a str, &'a str) {
+    (
+      tag.start.text,
+      tag.sigil.text,
+      tag.leading.text,
+      tag.content.text,
+      tag.trailing.text,
+      tag.closer.text,
+      tag.stop.text,
+    )
+  }
+
+  #[test]
+  fn derives_the_template() -> Result<()> {
+    let derived = Cst::parse(SOURCE)?.template()?.to_owned_template();
+
+    assert_eq!(Template::compile(SOURCE)?.to_owned_template(), derived);
+    Ok(())
+  }
+
+  #[test]
+  fn renaming_a_key_keeps_formatting() -> Result<()> {
+    let source = "{{# user }}\n  {{ user.name }}{{{user.name}}}\n{{/ user }}";
+    let cst = Cst::parse(source)?;
+
+    let mut renamed = source.to_string();
+    for tag in cst.tags().iter().rev() {
+      if tag.content.text == "user.name" {
+        renamed.replace_range(tag.content.span.range(), "user.title");
+      }
+    }
+
+    assert_eq!("{{# user }}\n  {{ user.title }}{{{user.title}}}\n{{/ user }}", renamed);
+    Ok(())
+  }
+
+  #[test]
+  fn fails_like_the_parser() {
+    assert!(matches!(Cst::parse("{{#a}}{{/b}}"), Err(Error::MismatchedClose { .. })));
+    assert!(matches!(Cst::parse("{{/a}}"), Err(Error::UnopenedClose { .. })));
+    assert!(matches!(Cst::parse("{{#a}}{{#b}}{{/b}}"), Err(Error::UnclosedSection { key, .. }) if key == "a"));
+  }
+
+  #[test]
+  fn delimiters_at_a_tag_being_typed() {
+    let source = "{{#a}}{{=<% %>=}} <%b%> <%c";
+
+    assert_eq!(("{{", "}}"), Cst::delimiters_at(source, 6));
+    assert_eq!(("<%", "%>"), Cst::delimiters_at(source, 17));
+    assert_eq!(("<%", "%>"), Cst::delimiters_at(source, source.len()));
+  }
+
+  #[test]
+  #[should_panic(expected = "not a character boundary")]
+  fn delimiters_at_inside_a_character() {
+    Cst::delimiters_at("{{é}}", 3);
+  }
+}
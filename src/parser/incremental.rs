@@ -0,0 +1,365 @@
+use std::ops::Range;
+use std::rc::Rc;
+
+use super::tag::{self, Action, ContextKind, Token};
+use crate::owned::OwnedTag;
+use crate::types::{Result, Template};
+
+#[derive(Debug, PartialEq, Eq)]
+struct Delimiters {
+  start: String,
+  stop: String,
+}
+
+/// What a token was read as, owning its text so that it outlasts edits
+/// elsewhere in the source.
+#[derive(Debug, Clone)]
+enum Read {
+  Text,
+  Tag(Box<OwnedTag>),
+  Section(Vec<String>, String),
+  Inverted(Vec<String>, String),
+  Parent(String),
+  Block(String),
+  Close(String),
+  Delimiters(Rc<Delimiters>),
+}
+
+impl Read {
+  fn new(action: &Action) -> Read {
+    let owned = |key: &[&str]| key.iter().map(|name| name.to_string()).collect();
+    match action {
+      Action::PushText => Read::Text,
+      Action::PushTag { tag } => Read::Tag(Box::new(OwnedTag::from(tag))),
+      Action::PushContext { kind, name } => match kind {
+        ContextKind::Section(key) => Read::Section(owned(key), name.to_string()),
+        ContextKind::Inverted(key) => Read::Inverted(owned(key), name.to_string()),
+        ContextKind::Parent(_) => Read::Parent(name.to_string()),
+        ContextKind::Block => Read::Block(name.to_string()),
+      },
+      Action::PopContext { name } => Read::Close(name.to_string()),
+      Action::ChangeDelimiter { start, stop } => Read::Delimiters(Rc::new(Delimiters {
+        start: start.to_string(),
+        stop: stop.to_string(),
+      })),
+    }
+  }
+
+  fn action(&self) -> Action<'_> {
+    fn borrowed(key: &[String]) -> Vec<&str> {
+      key.iter().map(String::as_str).collect()
+    }
+    match self {
+      Read::Text => Action::PushText,
+      Read::Tag(tag) => Action::PushTag {
+        tag: Box::new(tag.tag()),
+      },
+      Read::Section(key, name) => Action::PushContext {
+        kind: ContextKind::Section(borrowed(key)),
+        name,
+      },
+      Read::Inverted(key, name) => Action::PushContext {
+        kind: ContextKind::Inverted(borrowed(key)),
+        name,
+      },
+      Read::Parent(name) => Action::PushContext {
+        kind: ContextKind::Parent(""),
+        name,
+      },
+      Read::Block(name) => Action::PushContext {
+        kind: ContextKind::Block,
+        name,
+      },
+      Read::Close(name) => Action::PopContext { name },
+      Read::Delimiters(delimiters) => Action::ChangeDelimiter {
+        start: &delimiters.start,
+        stop: &delimiters.stop,
+      },
+    }
+  }
+}
+
+/// Where a token of the source lies, the delimiters it was read with, and
+/// what it was read as.
+#[derive(Debug, Clone)]
+struct Lexeme {
+  start: usize,
+  end: usize,
+  delimiters: Rc<Delimiters>,
+  read: Read,
+}
+
+/// A template source kept parsed through a series of edits. An edit only
+/// rescans the text from the token before it up to where the new tokens line
+/// up with the old ones again, under the same delimiters; past a changed
+/// `{{=<% %>=}}` that may be the end of the source.
+///
+/// Each token keeps what it was read as, so only the tokens in the rescanned
+/// window are read again. `template` still builds a new tree from all of them,
+/// in time linear in their number.
+#[derive(Debug, Clone)]
+pub struct IncrementalParse {
+  text: String,
+  lexemes: Vec<Lexeme>,
+  /// Where the source stopped tokenizing, if a tag there is malformed. The
+  /// lexemes cover the source up to this point.
+  failure: Option<usize>,
+}
+
+impl IncrementalParse {
+  pub fn new(text: &str) -> IncrementalParse {
+    let mut parse = IncrementalParse {
+      text: text.to_string(),
+      lexemes: vec![],
+      failure: None,
+    };
+    let (lexemes, failure) = parse.lex(0, default_delimiters(), None);
+    parse.lexemes = lexemes;
+    parse.failure = match failure {
+      Some(Failure::At(offset)) => Some(offset),
+      _ => None,
+    };
+    parse
+  }
+
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+
+  /// Replaces a byte range of the source and brings the parse up to date.
+  /// Panics if the range is out of bounds or splits a character, as
+  /// `String::replace_range` does.
+  pub fn edit(&mut self, range: Range<usize>, replacement: &str) {
+    self.text.replace_range(range.clone(), replacement);
+    let removed = range.end - range.start;
+    let shift = |offset: usize| offset + replacement.len() - removed;
+
+    // A text token looks ahead for the start delimiter, so the edit may change
+    // the token before the first one it touches.
+    let touched = self.lexemes.partition_point(|lexeme| lexeme.end < range.start);
+    let resume = touched.saturating_sub(1);
+    let (from, delimiters) = match self.lexemes.get(resume) {
+      Some(lexeme) => (lexeme.start, lexeme.delimiters.clone()),
+      None => (0, default_delimiters()),
+    };
+
+    let old = self.lexemes.split_off(resume.min(self.lexemes.len()));
+    let old_failure = self.failure.take();
+    let resync = Resync {
+      after: range.start + replacement.len(),
+      old: &old,
+      shift: &shift,
+      edit_end: range.end,
+    };
+    let (lexemes, failure) = self.lex(from, delimiters, Some(resync));
+    self.lexemes.extend(lexemes);
+    self.failure = match failure {
+      Some(Failure::At(offset)) => Some(offset),
+      Some(Failure::Resynced(index)) => {
+        self.lexemes.extend(old[index..].iter().map(|lexeme| Lexeme {
+          start: shift(lexeme.start),
+          end: shift(lexeme.end),
+          ..lexeme.clone()
+        }));
+        old_failure.map(shift)
+      }
+      None => None,
+    };
+  }
+
+  /// The delimiters in effect after a token, which a set delimiter tag changes.
+  fn delimiters_after(lexeme: &Lexeme) -> Rc<Delimiters> {
+    match &lexeme.read {
+      Read::Delimiters(delimiters) => delimiters.clone(),
+      _ => lexeme.delimiters.clone(),
+    }
+  }
+
+  /// Tokenizes from `from` until the end, a malformed tag, or the point where
+  /// the tokens meet the old ones again.
+  fn lex(
+    &self,
+    mut from: usize,
+    mut delimiters: Rc<Delimiters>,
+    resync: Option<Resync>,
+  ) -> (Vec<Lexeme>, Option<Failure>) {
+    let text = self.text.as_str();
+    let mut lexemes = vec![];
+    while from < text.len() {
+      if let Some(index) = resync.as_ref().and_then(|resync| resync.find(from, &delimiters)) {
+        return (lexemes, Some(Failure::Resynced(index)));
+      }
+      let (action, rest) = match tag::mustache_tag(text, &text[from..], &delimiters.start, &delimiters.stop) {
+        Ok(found) => found,
+        Err(_) => return (lexemes, Some(Failure::At(from))),
+      };
+      let end = text.len() - rest.len();
+      let lexeme = Lexeme {
+        start: from,
+        end,
+        delimiters: delimiters.clone(),
+        read: Read::new(&action),
+      };
+      delimiters = IncrementalParse::delimiters_after(&lexeme);
+      lexemes.push(lexeme);
+      from = end;
+    }
+    (lexemes, None)
+  }
+
+  /// The template as it stands, or the first error in the source.
+  pub fn template(&self) -> Result<Template<'_>> {
+    let tokens = self
+      .lexemes
+      .iter()
+      .map(|lexeme| Token {
+        action: lexeme.read.action(),
+        start: lexeme.start,
+        end: lexeme.end,
+        standalone: false,
+      })
+      .collect();
+    if let Some(offset) = self.failure {
+      let delimiters = match self.lexemes.last() {
+        Some(last) => IncrementalParse::delimiters_after(last),
+        None => default_delimiters(),
+      };
+      tag::mustache_tag(&self.text, &self.text[offset..], &delimiters.start, &delimiters.stop)?;
+    }
+    tag::build(&self.text, tokens, "{{", "}}")
+  }
+}
+
+fn default_delimiters() -> Rc<Delimiters> {
+  Rc::new(Delimiters {
+    start: "{{".to_string(),
+    stop: "}}".to_string(),
+  })
+}
+
+enum Failure {
+  /// A malformed tag starts here.
+  At(usize),
+  /// The tokens from this index of the old ones still hold.
+  Resynced(usize),
+}
+
+struct Resync<'o> {
+  /// Where the replacement ends in the new source.
+  after: usize,
+  /// The old tokens from where tokenizing resumed.
+  old: &'o [Lexeme],
+  shift: &'o dyn Fn(usize) -> usize,
+  /// Where the edit ended in the old source.
+  edit_end: usize,
+}
+
+impl<'o> Resync<'o> {
+  /// The old token starting at this new offset, if the edit lies behind it
+  /// and it was read with the same delimiters.
+  fn find(&self, offset: usize, delimiters: &Delimiters) -> Option<usize> {
+    if offset < self.after {
+      return None;
+    }
+    let behind = self.old.partition_point(|lexeme| lexeme.start < self.edit_end);
+    let index = behind
+      + self.old[behind..]
+        .binary_search_by_key(&offset, |lexeme| (self.shift)(lexeme.start))
+        .ok()?;
+    let lexeme = &self.old[index];
+    if *lexeme.delimiters == *delimiters {
+      Some(index)
+    } else {
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::types::Error;
+
+  fn same_as_full_parse(parse: &IncrementalParse) {
+    let expected = Template::compile(parse.text()).map(|template| template.to_owned_template());
+    let actual = parse.template().map(|template| template.to_owned_template());
+    match (expected, actual) {
+      (Ok(expected), Ok(actual)) => assert_eq!(expected, actual, "{:?}", parse.text()),
+      (Err(expected), Err(actual)) => assert_eq!(expected.to_string(), actual.to_string()),
+      (expected, actual) => panic!("{:?}: {:?} vs {:?}", parse.text(), expected.is_ok(), actual.is_ok()),
+    }
+  }
+
+  #[test]
+  fn edits_inside_text_and_tags() {
+    let mut parse = IncrementalParse::new("Hello {{name}}!\n{{#items}}\n  - {{.}}\n{{/items}}\n");
+    same_as_full_parse(&parse);
+
+    parse.edit(0..5, "Goodbye");
+    same_as_full_parse(&parse);
+    parse.edit(10..14, "user.name");
+    same_as_full_parse(&parse);
+    parse.edit(parse.text().len()..parse.text().len(), "{{! end }}");
+    same_as_full_parse(&parse);
+    assert_eq!("Goodbye {{user.name}}!\n{{#items}}\n  - {{.}}\n{{/items}}\n{{! end }}", parse.text());
+  }
+
+  #[test]
+  fn delimiter_changes_reach_the_rest() {
+    let mut parse = IncrementalParse::new("{{=<% %>=}}<%a%> {{b}} <%#c%><%/c%>");
+    same_as_full_parse(&parse);
+
+    // Undoing the delimiter change turns every later tag around.
+    parse.edit(0..11, "");
+    same_as_full_parse(&parse);
+    parse.edit(0..0, "{{=| |=}}");
+    same_as_full_parse(&parse);
+    parse.edit(3..4, "<%");
+    same_as_full_parse(&parse);
+  }
+
+  #[test]
+  fn forming_and_breaking_tags() {
+    let mut parse = IncrementalParse::new("a { b }} c {{d}}");
+    same_as_full_parse(&parse);
+
+    parse.edit(2..2, "{");
+    same_as_full_parse(&parse);
+    parse.edit(14..15, "");
+    same_as_full_parse(&parse);
+  }
+
+  #[test]
+  fn recovers_from_errors() {
+    let mut parse = IncrementalParse::new("{{#a}}{{b}}{{/a}} tail {{c}}");
+
+    parse.edit(9..9, " x");
+    assert!(matches!(parse.template(), Err(Error::BadKey { .. })));
+    same_as_full_parse(&parse);
+    parse.edit(20..20, "{{");
+    same_as_full_parse(&parse);
+    parse.edit(9..11, "");
+    same_as_full_parse(&parse);
+    assert_eq!("{{#a}}{{b}}{{/a}} {{tail {{c}}", parse.text());
+    parse.edit(18..20, "");
+    same_as_full_parse(&parse);
+    assert!(parse.template().is_ok());
+  }
+
+  #[test]
+  fn reuses_tokens_after_the_edit() {
+    let source = "{{a}} text {{b}} more {{c}}";
+    let mut parse = IncrementalParse::new(source);
+    let before = parse.lexemes.len();
+
+    parse.edit(6..10, "prose");
+    assert_eq!(before, parse.lexemes.len());
+    assert_eq!(12, parse.lexemes[2].start);
+    assert_eq!(17..23, parse.lexemes[3].start..parse.lexemes[3].end);
+    match &parse.lexemes[4].read {
+      Read::Tag(tag) => assert!(matches!(&**tag, OwnedTag::Variable(tag) if tag.key == ["c"])),
+      other => panic!("expected the tag read before the edit, got {:?}", other),
+    }
+    same_as_full_parse(&parse);
+  }
+}
@@ -1,5 +1,5 @@
-use super::core;
-use crate::types::{Result, Key};
+use super::core::{self, Miss, Result};
+use crate::types::Key;
 
 fn dot(text: &str) -> Result<((), &str)> {
   let (_, text0) = core::string(text, ".")?;
@@ -16,7 +16,7 @@ fn is_identifier_char(c: char) -> bool {
 
 fn identifier(text: &str) -> Result<(&str, &str)> {
   if !text.starts_with(is_identifier_start) {
-    return Err("Leading character for identifier not found".to_string());
+    return Err(Miss);
   }
 
   core::many_chars(text, is_identifier_char)
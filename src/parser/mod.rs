@@ -1,5 +1,10 @@
 mod core;
+mod cst;
+mod incremental;
 mod key;
 mod tag;
 
-pub use tag::template;
+pub use cst::{Cst, CstNode, CstSection, CstTag, CstTagKind, CstToken};
+pub use incremental::IncrementalParse;
+pub use tag::{template, template_with_delimiters};
+pub(crate) use key::key;
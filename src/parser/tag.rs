@@ -1,11 +1,21 @@
 use super::core;
-use crate::types::{ContextTag, DelimiterTag, Key, KeyTag, Result, Tag, Template, ValueTag};
+use crate::types::{
+  BlockTag, ContextTag, DelimiterTag, Error, Key, KeyTag, ParentTag, PartialTag, Position, Result,
+  Span, Tag, Template, ValueTag,
+};
 
-enum Action<'a> {
-  PushTag { tag: Tag<'a> },
-  PushInvertedContext { key: Key<'a> },
-  PushSectionContext { key: Key<'a> },
-  PopContext { key: Key<'a> },
+pub(super) enum ContextKind<'a> {
+  Section(Key<'a>),
+  Inverted(Key<'a>),
+  Parent(&'a str),
+  Block,
+}
+
+pub(super) enum Action<'a> {
+  PushText,
+  PushTag { tag: Box<Tag<'a>> },
+  PushContext { kind: ContextKind<'a>, name: &'a str },
+  PopContext { name: &'a str },
   ChangeDelimiter { start: &'a str, stop: &'a str },
 }
 
@@ -19,41 +29,40 @@ enum TagKind {
   Section,
   Comment,
   Partial,
+  Parent,
+  Block,
   SectionEnd,
 }
 
-fn start_of_tag<'a>(text: &'a str, start: &'a str) -> Result<(TagKind, &'a str)> {
+fn start_of_tag<'a>(text: &'a str, start: &str) -> (TagKind, &'a str) {
   if let Ok((_, text0)) = core::string(text, start) {
-    let (kind, text1) = core::take(text0, 1)?;
-    let token_kind = match kind {
-      "!" => TagKind::Comment,
-      ">" => TagKind::Partial,
-      "{" => TagKind::UnescapedWrapped,
-      "&" => TagKind::Unescaped,
-      "=" => TagKind::Delimiters,
-      "^" => TagKind::Inverted,
-      "#" => TagKind::Section,
-      "/" => TagKind::SectionEnd,
+    let token_kind = match text0.chars().next() {
+      Some('!') => TagKind::Comment,
+      Some('>') => TagKind::Partial,
+      Some('{') => TagKind::UnescapedWrapped,
+      Some('&') => TagKind::Unescaped,
+      Some('=') => TagKind::Delimiters,
+      Some('^') => TagKind::Inverted,
+      Some('#') => TagKind::Section,
+      Some('<') => TagKind::Parent,
+      Some('$') => TagKind::Block,
+      Some('/') => TagKind::SectionEnd,
       _ => {
-        return Ok((TagKind::Variable, text0));
+        return (TagKind::Variable, text0);
       }
     };
-    Ok((token_kind, text1))
+    (token_kind, &text0[1..])
   } else {
-    Ok((TagKind::Text, text))
+    (TagKind::Text, text)
   }
 }
 
-fn end_of_tag<'a>(text: &'a str, stop: &'a str) -> Result<(&'a str, &'a str)> {
-  core::string(text, stop)
+fn value_tag<'a>(text: &'a str, stop: &str) -> (&'a str, &'a str) {
+  let (value, text1) = text_tag(text, stop);
+  (value.trim(), text1)
 }
 
-fn value_tag<'a>(text: &'a str, stop: &'a str) -> Result<(&'a str, &'a str)> {
-  let (value, text1) = core::take_until(text, |text0| text0.starts_with(stop))?;
-  Ok((value.trim(), text1))
-}
-
-fn key_tag(text: &str) -> Result<(Key<'_>, &str)> {
+fn key_tag(text: &str) -> core::Result<(Key<'_>, &str)> {
   let (_, text0) = core::many_chars(text, char::is_whitespace)?;
   let (key, text1) = super::key::key(text0)?;
   let (_, text2) = core::many_chars(text1, char::is_whitespace)?;
@@ -64,157 +73,674 @@ fn is_delimiter_char(c: char) -> bool {
   !char::is_whitespace(c) && c != '='
 }
 
-fn delimiter_tag(text: &str) -> Result<((&str, &str), &str)> {
+fn delimiter_tag(text: &str) -> core::Result<((&str, &str), &str)> {
   let (_, text0) = core::many_chars(text, char::is_whitespace)?;
   let (start, text1) = core::some_chars(text0, is_delimiter_char)?;
   let (_, text2) = core::many_chars(text1, char::is_whitespace)?;
   let (stop, text3) = core::some_chars(text2, is_delimiter_char)?;
   let (_, text4) = core::many_chars(text3, char::is_whitespace)?;
-  Ok(((start, stop), text4))
+  let (_, text5) = core::string(text4, "=")?;
+  Ok(((start, stop), text5))
+}
+
+fn text_tag<'a>(text: &'a str, stop: &str) -> (&'a str, &'a str) {
+  core::take_until(text, |t| t.starts_with(stop)).unwrap_or((text, ""))
+}
+
+/// Stands in for the span of a tag until the tree is built, when tags are
+/// located in order.
+const UNLOCATED: Span = Span {
+  start: Position { offset: 0, line: 1, column: 1 },
+  end: Position { offset: 0, line: 1, column: 1 },
+};
+
+fn position(source: &str, text: &str) -> Position {
+  Position::locate(source, source.len() - text.len())
 }
 
-fn text_tag<'a>(text: &'a str, stop: &'a str) -> Result<(&'a str, &'a str)> {
-  core::take_until(text, |t| t.starts_with(stop))
+/// Tracks lines and columns through the source as tags are found in order, so
+/// locating each tag does not rescan the text before it.
+pub(super) struct Locator<'a> {
+  source: &'a str,
+  position: Position,
+}
+
+impl<'a> Locator<'a> {
+  pub(super) fn new(source: &'a str) -> Locator<'a> {
+    Locator {
+      source,
+      position: Position::locate(source, 0),
+    }
+  }
+
+  pub(super) fn span(&mut self, start: usize, end: usize) -> Span {
+    Span {
+      start: self.locate(start),
+      end: self.locate(end),
+    }
+  }
+
+  fn locate(&mut self, offset: usize) -> Position {
+    let lead = &self.source[self.position.offset..offset];
+    let position = &mut self.position;
+    match lead.rfind('\n') {
+      Some(newline) => {
+        position.line += lead.matches('\n').count();
+        position.column = lead[newline + 1..].chars().count() + 1;
+      }
+      None => position.column += lead.chars().count(),
+    }
+    position.offset = offset;
+    *position
+  }
 }
 
-fn mustache_tag<'a>(text: &'a str, start: &'a str, stop: &'a str) -> Result<(Action<'a>, &'a str)> {
-  let (kind, text0) = start_of_tag(text, start)?;
+pub(super) fn mustache_tag<'a>(
+  source: &'a str,
+  text: &'a str,
+  start: &'a str,
+  stop: &'a str,
+) -> Result<(Action<'a>, &'a str)> {
+  let (kind, text0) = start_of_tag(text, start);
+  let is_delimiters = matches!(kind, TagKind::Delimiters);
+  let bad_key = || Error::BadKey {
+    position: position(source, text0),
+  };
   let (action, text1) = match kind {
     TagKind::Text => {
-      let (value, text1) = text_tag(text0, start)?;
-      let tag = Tag::Text(ValueTag { value });
-      return Ok((Action::PushTag { tag }, text1));
+      let (_, text1) = text_tag(text0, start);
+      return Ok((Action::PushText, text1));
     }
     TagKind::Delimiters => {
-      let ((start, stop), text0) = delimiter_tag(text0)?;
-      let (_, text1) = core::string(text0, "=")?;
+      let ((start, stop), text1) = delimiter_tag(text0).map_err(|_| Error::BadDelimiter {
+        position: position(source, text0),
+      })?;
       (Action::ChangeDelimiter { start, stop }, text1)
     }
     TagKind::Inverted => {
-      let (key, text1) = key_tag(text0)?;
-      (Action::PushInvertedContext { key }, text1)
+      let (key, text1) = key_tag(text0).map_err(|_| bad_key())?;
+      let name = text0[..text0.len() - text1.len()].trim();
+      let kind = ContextKind::Inverted(key);
+      (Action::PushContext { kind, name }, text1)
     }
     TagKind::Section => {
-      let (key, text1) = key_tag(text0)?;
-      (Action::PushSectionContext { key }, text1)
+      let (key, text1) = key_tag(text0).map_err(|_| bad_key())?;
+      let name = text0[..text0.len() - text1.len()].trim();
+      let kind = ContextKind::Section(key);
+      (Action::PushContext { kind, name }, text1)
+    }
+    TagKind::Parent => {
+      let (name, text1) = value_tag(text0, stop);
+      let kind = ContextKind::Parent("");
+      (Action::PushContext { kind, name }, text1)
+    }
+    TagKind::Block => {
+      let (name, text1) = value_tag(text0, stop);
+      let kind = ContextKind::Block;
+      (Action::PushContext { kind, name }, text1)
     }
     TagKind::SectionEnd => {
-      let (key, text1) = key_tag(text0)?;
-      (Action::PopContext { key }, text1)
+      let (name, text1) = value_tag(text0, stop);
+      (Action::PopContext { name }, text1)
     }
     TagKind::UnescapedWrapped => {
-      let (key, text1) = key_tag(text0)?;
-      let (_, text2) = core::string(text1, "}")?;
-      let tag = Tag::Unescaped(KeyTag { key });
+      let (key, text1) = key_tag(text0).map_err(|_| bad_key())?;
+      let (_, text2) = core::string(text1, "}").map_err(|_| bad_key())?;
+      let tag = Box::new(Tag::Unescaped(KeyTag { key, span: UNLOCATED }));
       (Action::PushTag { tag }, text2)
     }
     TagKind::Unescaped => {
-      let (key, text1) = key_tag(text0)?;
-      let tag = Tag::Unescaped(KeyTag { key });
+      let (key, text1) = key_tag(text0).map_err(|_| bad_key())?;
+      let tag = Box::new(Tag::Unescaped(KeyTag { key, span: UNLOCATED }));
       (Action::PushTag { tag }, text1)
     }
     TagKind::Variable => {
-      let (key, text1) = key_tag(text0)?;
-      let tag = Tag::Variable(KeyTag { key });
+      let (key, text1) = key_tag(text0).map_err(|_| bad_key())?;
+      let tag = Box::new(Tag::Variable(KeyTag { key, span: UNLOCATED }));
       (Action::PushTag { tag }, text1)
     }
     TagKind::Comment => {
-      let (value, text2) = value_tag(text0, stop)?;
-      let tag = Tag::Comment(ValueTag { value });
+      let (value, text2) = value_tag(text0, stop);
+      let tag = Box::new(Tag::Comment(ValueTag {
+        value,
+        span: UNLOCATED,
+        standalone: false,
+      }));
       (Action::PushTag { tag }, text2)
     }
-    TagKind::Partial => {
-      let (value, text1) = value_tag(text0, stop)?;
-      let tag = Tag::Partial(ValueTag { value });
-      (Action::PushTag { tag }, text1)
+    TagKind::Partial => match text0.trim_start().strip_prefix('*') {
+      // A dynamic name `{{>*key}}` looks the partial's name up in the context.
+      Some(text1) => {
+        let (key, text2) = key_tag(text1).map_err(|_| bad_key())?;
+        let name = text1[..text1.len() - text2.len()].trim();
+        let tag = Box::new(Tag::Partial(PartialTag {
+          name,
+          key: Some(key),
+          indentation: "",
+          span: UNLOCATED,
+          standalone: false,
+        }));
+        (Action::PushTag { tag }, text2)
+      }
+      None => {
+        let (name, text1) = value_tag(text0, stop);
+        let tag = Box::new(Tag::Partial(PartialTag {
+          name,
+          key: None,
+          indentation: "",
+          span: UNLOCATED,
+          standalone: false,
+        }));
+        (Action::PushTag { tag }, text1)
+      }
+    },
+  };
+  match core::string(text1, stop) {
+    Ok((_, text2)) => Ok((action, text2)),
+    // The tag does close, so whatever sits before its delimiter is malformed.
+    Err(_) if text1.contains(stop) && is_delimiters => Err(Error::BadDelimiter {
+      position: position(source, text0),
+    }),
+    Err(_) if text1.contains(stop) => Err(bad_key()),
+    Err(_) => Err(Error::UnclosedTag {
+      position: position(source, text),
+      delimiter: stop.to_string(),
+    }),
+  }
+}
+
+pub(super) struct Token<'a> {
+  pub(super) action: Action<'a>,
+  pub(super) start: usize,
+  pub(super) end: usize,
+  /// Whether the tag stands alone on its line, set as the line is stripped.
+  pub(super) standalone: bool,
+}
+
+impl<'a> Action<'a> {
+  fn may_stand_alone(&self) -> bool {
+    match self {
+      Action::PushText => false,
+      Action::PushTag { tag } => matches!(**tag, Tag::Comment(_) | Tag::Partial(_)),
+      _ => true,
     }
+  }
+
+  /// Records the indentation of a standalone partial or parent, which is
+  /// prefixed to every line of the template it includes.
+  fn indent(&mut self, indentation: &'a str) {
+    match self {
+      Action::PushTag { tag } => {
+        if let Tag::Partial(partial) = &mut **tag {
+          partial.indentation = indentation;
+        }
+      }
+      Action::PushContext { kind: ContextKind::Parent(lead), .. } => *lead = indentation,
+      _ => (),
+    }
+  }
+}
+
+fn locate_tag(tag: &mut Tag, span: Span, standalone: bool) {
+  match tag {
+    Tag::Variable(tag) | Tag::Unescaped(tag) => tag.span = span,
+    Tag::Comment(tag) => {
+      tag.span = span;
+      tag.standalone = standalone;
+    }
+    Tag::Partial(tag) => {
+      tag.span = span;
+      tag.standalone = standalone;
+    }
+    _ => (),
+  }
+}
+
+fn is_inline_whitespace(c: char) -> bool {
+  c == ' ' || c == '\t'
+}
+
+pub(super) fn tokens<'a>(text: &'a str, mut start: &'a str, mut stop: &'a str) -> Result<Vec<Token<'a>>> {
+  let mut tokens = vec![];
+  let mut needle = text;
+
+  while !needle.is_empty() {
+    let offset = text.len() - needle.len();
+    let (action, text0) = mustache_tag(text, needle, start, stop)?;
+    needle = text0;
+    if let Action::ChangeDelimiter { start: left, stop: right } = action {
+      start = left;
+      stop = right;
+    }
+    tokens.push(Token {
+      action,
+      start: offset,
+      end: text.len() - needle.len(),
+      standalone: false,
+    });
+  }
+  Ok(tokens)
+}
+
+/// Whether each token opens or closes a parent or a block, by the contexts
+/// open before it.
+fn inheritance_tags(tokens: &[Token]) -> Vec<bool> {
+  let mut open = vec![];
+  tokens
+    .iter()
+    .map(|token| match &token.action {
+      Action::PushContext { kind, .. } => {
+        let inheritance = matches!(kind, ContextKind::Parent(_) | ContextKind::Block);
+        open.push(inheritance);
+        inheritance
+      }
+      Action::PopContext { .. } => open.pop().unwrap_or(false),
+      _ => false,
+    })
+    .collect()
+}
+
+/// The tag leading a line that holds nothing else but whitespace, if the line
+/// is standalone. As in the spec, that takes a single tag, except that the
+/// tags of parents and blocks may share their line.
+fn standalone_lead(
+  text: &str,
+  line: &[Token],
+  inheritance: &[bool],
+  line_start: usize,
+  line_end: usize,
+) -> Option<usize> {
+  let mut tags = (0..line.len()).filter(|&index| !matches!(line[index].action, Action::PushText));
+  let lead = tags.next()?;
+  if !line[lead].action.may_stand_alone() || !tags.all(|index| inheritance[lead] && inheritance[index]) {
+    return None;
+  }
+
+  let is_blank = line
+    .iter()
+    .filter(|token| matches!(token.action, Action::PushText))
+    .all(|token| {
+      let from = token.start.max(line_start);
+      let piece = &text[from..token.end.min(line_end).max(from)];
+      let piece = piece.strip_suffix('\n').map_or(piece, |p| p.strip_suffix('\r').unwrap_or(p));
+      piece.chars().all(is_inline_whitespace)
+    });
+  if is_blank {
+    Some(lead)
+  } else {
+    None
+  }
+}
+
+/// Removes the whitespace and line ending around a standalone line. The
+/// indentation moves onto the tag leading the line, for partials and parents
+/// to apply to the template they include.
+fn strip_line<'a>(text: &'a str, line: &mut [Token<'a>], inheritance: &[bool], line_start: usize, line_end: usize) {
+  let lead = match standalone_lead(text, line, inheritance, line_start, line_end) {
+    Some(lead) => lead,
+    None => return,
   };
-  let (_, text2) = end_of_tag(text1, stop)?;
-  Ok((action, text2))
+  let indentation = &text[line_start.min(line[lead].start)..line[lead].start];
+  line[lead].action.indent(indentation);
+  for token in line.iter_mut() {
+    if !matches!(token.action, Action::PushText) {
+      token.standalone = true;
+      continue;
+    }
+    if token.start < line_start {
+      token.end = token.end.min(line_start);
+    } else if token.end > line_end {
+      token.start = line_end;
+    } else {
+      token.end = token.start;
+    }
+  }
 }
 
-pub fn template<'a>(text: &'a str) -> Result<(Template<'a>, &'a str)> {
-  enum ContextKind {
-    Inverted,
-    Section,
+/// Strips each standalone line. A text token holding line breaks ends one
+/// line and starts the next.
+fn strip_standalone<'a>(text: &'a str, tokens: &mut [Token<'a>]) {
+  let inheritance = inheritance_tags(tokens);
+  let mut line_start = 0;
+  let mut first = 0;
+  for i in 0..tokens.len() {
+    if !matches!(tokens[i].action, Action::PushText) {
+      continue;
+    }
+    let value = &text[tokens[i].start..tokens[i].end];
+    if let (Some(head), Some(tail)) = (value.find('\n'), value.rfind('\n')) {
+      let line_end = tokens[i].start + head + 1;
+      let next_start = tokens[i].start + tail + 1;
+      strip_line(text, &mut tokens[first..=i], &inheritance[first..=i], line_start, line_end);
+      line_start = next_start;
+      first = i;
+    }
   }
+  strip_line(text, &mut tokens[first..], &inheritance[first..], line_start, text.len());
+}
+
+pub fn template(text: &str) -> Result<(Template<'_>, &str)> {
+  template_with_delimiters(text, "{{", "}}")
+}
+
+pub fn template_with_delimiters<'a>(
+  text: &'a str,
+  start: &'a str,
+  stop: &'a str,
+) -> Result<(Template<'a>, &'a str)> {
+  let tokens = tokens(text, start, stop)?;
+  Ok((build(text, tokens, start, stop)?, ""))
+}
 
+/// Strips standalone lines from the tokens of `text` and nests them into a
+/// template, starting with the given delimiters.
+pub(super) fn build<'a>(
+  text: &'a str,
+  mut tokens: Vec<Token<'a>>,
+  mut start: &'a str,
+  mut stop: &'a str,
+) -> Result<Template<'a>> {
   struct Context<'a> {
-    kind: ContextKind,
-    key: Vec<&'a str>,
+    kind: ContextKind<'a>,
+    name: &'a str,
     tags: Vec<Tag<'a>>,
+    span: Span,
+    standalone: bool,
+    body: usize,
+    delimiters: DelimiterTag<'a>,
   }
 
+  let mut locator = Locator::new(text);
+  let origin = locator.span(0, 0);
+  let mut delimiters_span = origin;
+  let mut delimiters_standalone = false;
   let mut stack: Vec<Context<'a>> = vec![];
   let mut context: Context<'a> = Context {
-    kind: ContextKind::Section,
-    key: vec![],
+    kind: ContextKind::Section(vec![]),
+    name: "",
     tags: vec![],
+    span: origin,
+    standalone: false,
+    body: 0,
+    delimiters: DelimiterTag {
+      start,
+      stop,
+      span: origin,
+      standalone: false,
+    },
   };
-  let mut start = "{{";
-  let mut stop = "}}";
-  let mut needle = text;
+  strip_standalone(text, &mut tokens);
 
-  while !needle.is_empty() {
-    let (action, text0) = mustache_tag(needle, start, stop)?;
-    needle = text0;
+  for Token {
+    action,
+    start: offset,
+    end,
+    standalone,
+  } in tokens
+  {
+    let span = locator.span(offset, end);
     match action {
-      Action::PushTag { tag } => {
-        context.tags.push(tag);
+      Action::PushText => {
+        if offset < end {
+          let value = &text[offset..end];
+          context.tags.push(Tag::Text(ValueTag {
+            value,
+            span,
+            standalone: false,
+          }));
+        }
       }
-      Action::PushInvertedContext { key } => {
-        stack.push(context);
-        context = Context {
-          kind: ContextKind::Inverted,
-          key,
-          tags: vec![],
-        };
+      Action::PushTag { mut tag } => {
+        locate_tag(&mut tag, span, standalone);
+        context.tags.push(*tag);
       }
-      Action::PushSectionContext { key } => {
+      Action::PushContext { kind, name } => {
         stack.push(context);
         context = Context {
-          kind: ContextKind::Section,
-          key,
+          kind,
+          name,
           tags: vec![],
+          span,
+          standalone,
+          body: end,
+          delimiters: DelimiterTag {
+            start,
+            stop,
+            span: delimiters_span,
+            standalone: delimiters_standalone,
+          },
         };
       }
-      Action::PopContext { key } => {
-        if context.key != key {
-          return Err(format!(
-            "Key mismatch for section: lead={} trail={}",
-            context.key.join("."),
-            key.join(".")
-          ));
-        }
+      Action::PopContext { name } => {
+        let mut parent_context = match stack.pop() {
+          Some(parent_context) => parent_context,
+          None => {
+            return Err(Error::UnopenedClose {
+              position: span.start,
+              key: name.to_string(),
+            })
+          }
+        };
 
-        let mut parent_context = stack
-          .pop()
-          .ok_or_else(|| format!("No parent context found: {}", key.join(".")))?;
-
-        match context.kind {
-          ContextKind::Section => parent_context.tags.push(Tag::Section(ContextTag {
-            key: context.key,
-            tags: context.tags,
-          })),
-          ContextKind::Inverted => parent_context.tags.push(Tag::Inverted(ContextTag {
-            key: context.key,
-            tags: context.tags,
-          })),
+        if context.name != name {
+          return Err(Error::MismatchedClose {
+            position: span.start,
+            open: context.name.to_string(),
+            close: name.to_string(),
+          });
         }
+
+        let tags = context.tags;
+        let source = &text[context.body..offset];
+        let delimiters = context.delimiters;
+        let (open, close) = (context.span, span);
+        let close_standalone = standalone;
+        let standalone = context.standalone;
+        parent_context.tags.push(match context.kind {
+          ContextKind::Section(key) => Tag::Section(ContextTag {
+            key,
+            tags,
+            source,
+            delimiters,
+            span: open,
+            close,
+            standalone,
+            close_standalone,
+          }),
+          ContextKind::Inverted(key) => Tag::Inverted(ContextTag {
+            key,
+            tags,
+            source,
+            delimiters,
+            span: open,
+            close,
+            standalone,
+            close_standalone,
+          }),
+          ContextKind::Parent(indentation) => Tag::Parent(ParentTag {
+            name,
+            indentation,
+            tags,
+            span: open,
+            close,
+            standalone,
+            close_standalone,
+          }),
+          ContextKind::Block => Tag::Block(BlockTag {
+            name,
+            tags,
+            span: open,
+            close,
+            standalone,
+            close_standalone,
+          }),
+        });
         context = parent_context;
       }
-      Action::ChangeDelimiter {
-        start: left,
-        stop: right,
-      } => {
+      Action::ChangeDelimiter { start: left, stop: right } => {
         start = left;
         stop = right;
+        delimiters_span = span;
+        delimiters_standalone = standalone;
         context.tags.push(Tag::Delimiters(DelimiterTag {
-          start: left,
-          stop: right,
+          start,
+          stop,
+          span,
+          standalone,
         }));
       }
     }
   }
-  Ok((Template { tags: context.tags }, needle))
+  if !stack.is_empty() {
+    return Err(Error::UnclosedSection {
+      position: context.span.start,
+      key: context.name.to_string(),
+    });
+  }
+  Ok(Template {
+    tags: context.tags,
+    escaper: None,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn error(text: &str) -> Error {
+    match template(text) {
+      Ok(_) => panic!("template parsed: {:?}", text),
+      Err(err) => err,
+    }
+  }
+
+  #[test]
+  fn parent_with_blocks() -> Result<()> {
+    let (template, _) = template("{{<layout/page}}\n  {{$title}}Home{{/title}}\n{{/layout/page}}")?;
+
+    match template.tags.as_slice() {
+      [Tag::Parent(parent)] => {
+        assert_eq!("layout/page", parent.name);
+        match parent.tags.as_slice() {
+          [Tag::Text(_), Tag::Block(block), Tag::Text(_)] => assert_eq!("title", block.name),
+          _ => panic!("expected a block between text"),
+        }
+      }
+      _ => panic!("expected a single parent"),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn dynamic_partial_names() -> Result<()> {
+    let (template, _) = template("{{>*card.kind}}{{> * card }}{{>card}}")?;
+
+    match template.tags.as_slice() {
+      [Tag::Partial(first), Tag::Partial(second), Tag::Partial(plain)] => {
+        assert_eq!(Some(vec!["card", "kind"]), first.key);
+        assert_eq!(Some(vec!["card"]), second.key);
+        assert_eq!("card", second.name);
+        assert_eq!(None, plain.key);
+        assert_eq!("card", plain.name);
+      }
+      _ => panic!("expected three partials"),
+    }
+    assert!(matches!(error("{{>*card kind}}"), Error::BadKey { .. }));
+    assert!(matches!(error("{{>*}}"), Error::BadKey { .. }));
+    Ok(())
+  }
+
+  #[test]
+  fn spans_cover_tags_and_delimiters() -> Result<()> {
+    let source = "{{#a}}\n  {{ b }}\n{{/a}}{{=<% %>=}}<%! c %>";
+    let (template, _) = template(source)?;
+
+    match template.tags.as_slice() {
+      [Tag::Section(section), Tag::Delimiters(delimiters), Tag::Comment(comment)] => {
+        assert_eq!(0..6, section.span.range());
+        assert_eq!(17..23, section.close.range());
+        assert_eq!(Position { offset: 17, line: 3, column: 1 }, section.close.start);
+        assert_eq!(0..23, template.tags[0].span().range());
+        match section.tags.as_slice() {
+          [Tag::Text(indent), Tag::Variable(variable), Tag::Text(newline)] => {
+            assert_eq!(7..9, indent.span.range());
+            assert_eq!("{{ b }}", &source[variable.span.range()]);
+            assert_eq!(Position { offset: 9, line: 2, column: 3 }, variable.span.start);
+            assert_eq!(16..17, newline.span.range());
+          }
+          _ => panic!("expected a variable between text"),
+        }
+        assert_eq!("{{=<% %>=}}", &source[delimiters.span.range()]);
+        assert_eq!("<%! c %>", &source[comment.span.range()]);
+        assert_eq!(Position { offset: 42, line: 3, column: 26 }, comment.span.end);
+      }
+      _ => panic!("expected a section, delimiters and a comment"),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn tag_pairs_do_not_stand_alone() -> Result<()> {
+    let (template, _) = template("  {{#alpha}}{{/alpha}}  \r\nbeta")?;
+
+    match template.tags.as_slice() {
+      [Tag::Text(lead), Tag::Section(_), Tag::Text(text)] => {
+        assert_eq!("  ", lead.value);
+        assert_eq!("  \r\nbeta", text.value);
+      }
+      _ => panic!("expected a section between text"),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn unclosed_tag() {
+    match error("alpha\n  {{beta") {
+      Error::UnclosedTag { position, delimiter } => {
+        assert_eq!(Position { offset: 8, line: 2, column: 3 }, position);
+        assert_eq!("}}", delimiter);
+      }
+      err => panic!("unexpected error: {}", err),
+    }
+  }
+
+  #[test]
+  fn unclosed_section() {
+    match error("{{#alpha}}{{#beta}}{{/beta}}") {
+      Error::UnclosedSection { position, key } => {
+        assert_eq!(0, position.offset);
+        assert_eq!("alpha", key);
+      }
+      err => panic!("unexpected error: {}", err),
+    }
+  }
+
+  #[test]
+  fn mismatched_close() {
+    match error("{{#alpha}}\n{{/beta}}") {
+      Error::MismatchedClose { position, open, close } => {
+        assert_eq!(Position { offset: 11, line: 2, column: 1 }, position);
+        assert_eq!("alpha", open);
+        assert_eq!("beta", close);
+      }
+      err => panic!("unexpected error: {}", err),
+    }
+  }
+
+  #[test]
+  fn unopened_close() {
+    assert!(matches!(error("é{{/alpha}}"), Error::UnopenedClose { position, .. } if position.column == 2));
+  }
+
+  #[test]
+  fn bad_delimiter() {
+    assert!(matches!(error("{{=<% %> %>=}}"), Error::BadDelimiter { .. }));
+    assert!(matches!(error("{{=<%=}}"), Error::BadDelimiter { .. }));
+  }
+
+  #[test]
+  fn bad_key() {
+    assert!(matches!(error("{{alpha beta}}"), Error::BadKey { position } if position.offset == 2));
+    assert!(matches!(error("{{#}}"), Error::BadKey { .. }));
+  }
 }
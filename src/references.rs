@@ -0,0 +1,145 @@
+use super::types::{Key, Span, Tag, Template};
+
+/// How a template uses a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+  /// `{{key}}`
+  Variable,
+  /// `{{{key}}}` or `{{&key}}`
+  Unescaped,
+  /// `{{#key}}`
+  Section,
+  /// `{{^key}}`
+  Inverted,
+  /// `{{>*key}}`, whose value names a partial.
+  Partial,
+}
+
+/// A section or inverted section around a reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope<'a> {
+  pub key: Key<'a>,
+  pub inverted: bool,
+}
+
+/// A key the template looks up. The implicit iterator `{{.}}` has an empty key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference<'a> {
+  pub key: Key<'a>,
+  pub kind: ReferenceKind,
+  /// The sections the key is nested in, outermost first.
+  pub scopes: Vec<Scope<'a>>,
+  pub span: Span,
+}
+
+/// Everything a template refers to, in the order it appears.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct References<'a> {
+  pub keys: Vec<Reference<'a>>,
+  /// The names of the partials and parents included by name, each once.
+  pub partials: Vec<&'a str>,
+}
+
+impl<'a> References<'a> {
+  fn key(&mut self, key: &Key<'a>, kind: ReferenceKind, scopes: &[Scope<'a>], span: Span) {
+    self.keys.push(Reference {
+      key: key.clone(),
+      kind,
+      scopes: scopes.to_vec(),
+      span,
+    });
+  }
+
+  fn partial(&mut self, name: &'a str) {
+    if !self.partials.contains(&name) {
+      self.partials.push(name);
+    }
+  }
+
+  fn walk(&mut self, tags: &[Tag<'a>], scopes: &mut Vec<Scope<'a>>) {
+    for tag in tags {
+      match tag {
+        Tag::Variable(tag) => self.key(&tag.key, ReferenceKind::Variable, scopes, tag.span),
+        Tag::Unescaped(tag) => self.key(&tag.key, ReferenceKind::Unescaped, scopes, tag.span),
+        Tag::Section(section) | Tag::Inverted(section) => {
+          let inverted = matches!(tag, Tag::Inverted(_));
+          let kind = if inverted {
+            ReferenceKind::Inverted
+          } else {
+            ReferenceKind::Section
+          };
+          self.key(&section.key, kind, scopes, section.span);
+          scopes.push(Scope {
+            key: section.key.clone(),
+            inverted,
+          });
+          self.walk(&section.tags, scopes);
+          scopes.pop();
+        }
+        Tag::Partial(partial) => match &partial.key {
+          Some(key) => self.key(key, ReferenceKind::Partial, scopes, partial.span),
+          None => self.partial(partial.name),
+        },
+        Tag::Parent(parent) => {
+          self.partial(parent.name);
+          self.walk(&parent.tags, scopes);
+        }
+        Tag::Block(block) => self.walk(&block.tags, scopes),
+        Tag::Text(_) | Tag::Comment(_) | Tag::Delimiters(_) => (),
+      }
+    }
+  }
+}
+
+impl<'a> Template<'a> {
+  /// Lists the keys and partials the template refers to, without rendering
+  /// it or loading any partials.
+  pub fn references(&self) -> References<'a> {
+    let mut references = References::default();
+    references.walk(&self.tags, &mut vec![]);
+    references
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Result;
+
+  #[test]
+  fn keys_with_their_sections() -> Result<()> {
+    let template = Template::compile(
+      "{{title}}{{#items}}{{{name}}}{{^price.amount}}free{{/price.amount}}{{/items}}{{>*kind}}",
+    )?;
+
+    let keys: Vec<_> = template
+      .references()
+      .keys
+      .into_iter()
+      .map(|reference| {
+        let scopes: Vec<_> = reference.scopes.iter().map(|scope| scope.key.join(".")).collect();
+        (reference.key.join("."), reference.kind, scopes)
+      })
+      .collect();
+
+    assert_eq!(
+      vec![
+        ("title".to_string(), ReferenceKind::Variable, vec![]),
+        ("items".to_string(), ReferenceKind::Section, vec![]),
+        ("name".to_string(), ReferenceKind::Unescaped, vec!["items".to_string()]),
+        ("price.amount".to_string(), ReferenceKind::Inverted, vec!["items".to_string()]),
+        ("kind".to_string(), ReferenceKind::Partial, vec![]),
+      ],
+      keys
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn partials_by_name() -> Result<()> {
+    let template = Template::compile("{{>header}}{{<layout}}{{$body}}{{>row}}{{/body}}{{/layout}}{{>header}}")?;
+
+    assert_eq!(vec!["header", "layout", "row"], template.references().partials);
+    Ok(())
+  }
+}
@@ -1,39 +1,56 @@
-use super::types::{Value, KeySlice, Result};
+use super::context::Context;
+use super::parser;
+use super::types::KeySlice;
+
+/// A key that the context stack could not resolve.
+#[derive(Debug)]
+pub struct Miss;
+
+pub type Result<T> = std::result::Result<T, Miss>;
 
 fn resolve_key<'a>(
-  context: &'a Value,
+  context: &'a dyn Context,
   key: &KeySlice<'_>,
-) -> Result<&'a Value> {
+) -> Result<&'a dyn Context> {
   let mut root = context;
   for prop in key {
     root = root
       .get(prop)
-      .ok_or_else(|| format!("context miss for key '{}' ", key.join(".")))?;
+      .ok_or(Miss)?;
   }
   Ok(root)
 }
 
-fn resolve_hit<'a>(
-  context: &'a Value,
+fn resolve_hit(
+  context: &dyn Context,
   key: &KeySlice<'_>,
 ) -> bool {
-  match key.get(0) {
+  match key.first() {
     Some(lead) => context.get(lead).is_some(),
     None => true
   }
 }
 
 pub fn resolve<'a>(
-  context_stack: &[&'a Value],
+  context_stack: &[&'a dyn Context],
   key: &KeySlice<'_>,
-) -> Result<&'a Value> {
-  for context in context_stack.iter().rev() {
+) -> Result<&'a dyn Context> {
+  for &context in context_stack.iter().rev() {
     if resolve_hit(context, key) {
       return resolve_key(context, key);
     }
   }
 
-  Err(format!("resolve miss for key '{}' ", key.join(".")))
+  Err(Miss)
+}
+
+/// Looks a key such as `user.name` or `.` up a context stack, innermost last,
+/// the way a render does, for tools that follow keys outside a render.
+pub fn lookup<'a>(context_stack: &[&'a dyn Context], key: &str) -> Option<&'a dyn Context> {
+  match parser::key(key) {
+    Ok((key, "")) => resolve(context_stack, &key).ok(),
+    _ => None,
+  }
 }
 
 #[cfg(test)]
@@ -53,7 +70,7 @@ mod tests {
     let key = vec!["alpha", "beta"];
 
     let value = resolve_key(&context, &key)?;
-    assert_eq!(value, &json!("gamma"));
+    assert_eq!(Some("gamma"), value.text().as_deref());
     Ok(())
   }
 
@@ -64,7 +81,7 @@ mod tests {
 
     let value = resolve_key(&context, &key)?;
 
-    assert_eq!(value, &json!(4));
+    assert_eq!(Some("4"), value.text().as_deref());
     Ok(())
   }
 
@@ -75,7 +92,7 @@ mod tests {
 
     let value = resolve_key(&context, &key)?;
 
-    assert_eq!(value, &json!({ "test": 4 }));
+    assert_eq!(Some("4"), value.get("test").and_then(|test| test.text()).as_deref());
     Ok(())
   }
 
@@ -86,4 +103,16 @@ mod tests {
 
     assert!(resolve_key(&context, &key).is_err());
   }
+
+  #[test]
+  fn lookup_parses_keys() {
+    let outer = json!({ "user": { "name": "Ada" }, "title": "T" });
+    let inner = json!({ "user": {} });
+    let stack: Vec<&dyn Context> = vec![&outer, &inner];
+
+    assert!(lookup(&stack, "user.name").is_none());
+    assert_eq!(Some("T"), lookup(&stack, "title").and_then(|title| title.text()).as_deref());
+    assert!(lookup(&stack, ".").and_then(|value| value.get("user")).is_some());
+    assert!(lookup(&stack, "title.").is_none());
+  }
 }
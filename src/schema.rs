@@ -0,0 +1,199 @@
+use serde_json::{json, Map};
+use std::collections::BTreeMap;
+
+use super::types::{KeySlice, Tag, Template, Value};
+
+/// What the template does with one name in the data.
+#[derive(Default)]
+struct Shape {
+  properties: BTreeMap<String, Shape>,
+  /// Rendered as text.
+  scalar: bool,
+  /// Opens a section, so it may be anything, or objects if keys are read in it.
+  section: bool,
+  /// Its items are rendered with `{{.}}`.
+  implicit: bool,
+}
+
+impl Shape {
+  fn at(&mut self, path: &[String]) -> &mut Shape {
+    path.iter().fold(self, |shape, name| shape.properties.entry(name.clone()).or_default())
+  }
+
+  fn object(&self) -> Value {
+    let properties: Map<String, Value> = self
+      .properties
+      .iter()
+      .map(|(name, shape)| (name.clone(), shape.schema()))
+      .collect();
+    json!({ "type": "object", "properties": properties })
+  }
+
+  fn schema(&self) -> Value {
+    let scalar = json!({ "type": ["string", "number", "boolean", "null"] });
+    let item = if !self.properties.is_empty() {
+      Some(self.object())
+    } else if self.implicit {
+      Some(scalar.clone())
+    } else {
+      None
+    };
+    // A name used both ways may hold either.
+    let mut shapes = vec![];
+    if self.scalar && item.as_ref() != Some(&scalar) {
+      shapes.push(scalar);
+    }
+    match item {
+      Some(item) if self.section => {
+        shapes.push(item.clone());
+        shapes.push(json!({ "type": "array", "items": item }));
+      }
+      Some(item) => shapes.push(item),
+      // A section that only tests its value takes any value.
+      None if self.section => return json!({}),
+      None => (),
+    }
+    match shapes.len() {
+      // Only ever tested by an inverted section.
+      0 => json!({}),
+      1 => shapes.remove(0),
+      _ => json!({ "anyOf": shapes }),
+    }
+  }
+}
+
+struct Inferrer {
+  root: Shape,
+  /// Where each open section's items sit in the data, innermost last.
+  scopes: Vec<Vec<String>>,
+}
+
+impl Inferrer {
+  /// The path of a key, taken to belong to the innermost section's items,
+  /// since the template alone cannot tell which context a key resolves in.
+  fn path(&self, key: &KeySlice) -> Vec<String> {
+    let mut path = self.scopes.last().cloned().unwrap_or_default();
+    path.extend(key.iter().map(|name| name.to_string()));
+    path
+  }
+
+  fn walk(&mut self, tags: &[Tag]) {
+    for tag in tags {
+      match tag {
+        Tag::Variable(tag) | Tag::Unescaped(tag) => {
+          let path = self.path(&tag.key);
+          let shape = self.root.at(&path);
+          if tag.key.is_empty() && !self.scopes.is_empty() {
+            shape.implicit = true;
+          } else {
+            shape.scalar = true;
+          }
+        }
+        Tag::Section(section) => {
+          let path = self.path(&section.key);
+          self.root.at(&path).section = true;
+          self.scopes.push(path);
+          self.walk(&section.tags);
+          self.scopes.pop();
+        }
+        Tag::Inverted(section) => {
+          let path = self.path(&section.key);
+          self.root.at(&path);
+          self.walk(&section.tags);
+        }
+        Tag::Parent(parent) => self.walk(&parent.tags),
+        Tag::Block(block) => self.walk(&block.tags),
+        Tag::Text(_) | Tag::Comment(_) | Tag::Partial(_) | Tag::Delimiters(_) => (),
+      }
+    }
+  }
+}
+
+impl<'a> Template<'a> {
+  /// Infers a JSON Schema for the data the template renders. Sections holding
+  /// keys become objects or lists of objects, keys rendered as text become
+  /// scalars or null, sections holding neither take any value, and dotted keys
+  /// nest. Nothing is required, since missing keys render as nothing; partials
+  /// are not followed.
+  pub fn schema(&self) -> Value {
+    let mut inferrer = Inferrer {
+      root: Shape::default(),
+      scopes: vec![],
+    };
+    inferrer.walk(&self.tags);
+    let mut schema = inferrer.root.object();
+    schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    schema
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Result;
+
+  #[test]
+  fn infers_nested_shapes() -> Result<()> {
+    let template = Template::compile(
+      "{{title}} {{author.name}}{{#admin}}!{{/admin}}{{^draft}}live{{/draft}}\
+       {{#items}}{{name}}{{#tags}}{{.}}{{/tags}}{{/items}}",
+    )?;
+
+    let scalar = json!({ "type": ["string", "number", "boolean", "null"] });
+    let item = json!({
+      "type": "object",
+      "properties": {
+        "name": scalar,
+        "tags": { "anyOf": [scalar, { "type": "array", "items": scalar }] },
+      },
+    });
+    assert_eq!(
+      json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "type": "object",
+        "properties": {
+          "admin": {},
+          "author": { "type": "object", "properties": { "name": scalar } },
+          "draft": {},
+          "items": { "anyOf": [item, { "type": "array", "items": item }] },
+          "title": scalar,
+        },
+      }),
+      template.schema()
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn sections_testing_only_their_value_take_any_value() -> Result<()> {
+    let schema = Template::compile("{{#admin}}!{{/admin}}{{#count}}{{/count}}")?.schema();
+
+    assert_eq!(json!({}), schema["properties"]["admin"]);
+    assert_eq!(json!({}), schema["properties"]["count"]);
+    Ok(())
+  }
+
+  #[test]
+  fn merges_names_rendered_and_opened() -> Result<()> {
+    let schema = Template::compile("{{user}}{{#user}}{{name}}{{/user}}{{#flag}}{{/flag}}{{flag}}")?.schema();
+
+    let scalar = json!({ "type": ["string", "number", "boolean", "null"] });
+    let item = json!({ "type": "object", "properties": { "name": scalar } });
+    assert_eq!(
+      json!({ "anyOf": [scalar, item, { "type": "array", "items": item }] }),
+      schema["properties"]["user"]
+    );
+    assert_eq!(json!({}), schema["properties"]["flag"]);
+    Ok(())
+  }
+
+  #[test]
+  fn scalars_may_be_null() -> Result<()> {
+    let schema = Template::compile("{{title}}{{#tags}}{{.}}{{/tags}}")?.schema();
+
+    let types = json!(["string", "number", "boolean", "null"]);
+    assert_eq!(types, schema["properties"]["title"]["type"]);
+    assert_eq!(types, schema["properties"]["tags"]["anyOf"][1]["items"]["type"]);
+    Ok(())
+  }
+}
@@ -1,3 +1,9 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::{error, fmt, io};
+
+use super::escape::Escaper;
+
 pub type Key<'a> = Vec<&'a str>;
 pub type KeySlice<'a> = [&'a str];
 pub type Value = serde_json::Value;
@@ -5,19 +11,80 @@ pub type Value = serde_json::Value;
 pub struct ContextTag<'a> {
   pub key: Key<'a>,
   pub tags: Vec<Tag<'a>>,
+  /// The unprocessed text between the opening and closing tags.
+  pub source: &'a str,
+  /// The delimiters in effect where the section opens.
+  pub delimiters: DelimiterTag<'a>,
+  /// The opening tag, delimiters included.
+  pub span: Span,
+  /// The closing tag, delimiters included.
+  pub close: Span,
+  /// Whether the tag stands alone on its line, which the parser strips.
+  pub standalone: bool,
+  /// Whether the closing tag stands alone on its line.
+  pub close_standalone: bool,
 }
 
 pub struct KeyTag<'a> {
   pub key: Key<'a>,
+  pub span: Span,
 }
 
 pub struct ValueTag<'a> {
   pub value: &'a str,
+  pub span: Span,
+  /// Whether a comment stands alone on its line. Never set for text.
+  pub standalone: bool,
+}
+
+pub struct PartialTag<'a> {
+  pub name: &'a str,
+  /// For a dynamic name `{{>*key}}`, the key whose value names the partial.
+  /// The name then holds the key as written.
+  pub key: Option<Key<'a>>,
+  /// The whitespace before a standalone partial, prefixed to each of its lines.
+  pub indentation: &'a str,
+  pub span: Span,
+  /// Whether the tag stands alone on its line, which the parser strips.
+  pub standalone: bool,
+}
+
+pub struct ParentTag<'a> {
+  pub name: &'a str,
+  /// The whitespace before a standalone parent, prefixed to each of its lines.
+  pub indentation: &'a str,
+  pub tags: Vec<Tag<'a>>,
+  /// The opening tag, delimiters included.
+  pub span: Span,
+  /// The closing tag, delimiters included.
+  pub close: Span,
+  /// Whether the tag stands alone on its line, which the parser strips.
+  pub standalone: bool,
+  /// Whether the closing tag stands alone on its line.
+  pub close_standalone: bool,
+}
+
+pub struct BlockTag<'a> {
+  pub name: &'a str,
+  pub tags: Vec<Tag<'a>>,
+  /// The opening tag, delimiters included.
+  pub span: Span,
+  /// The closing tag, delimiters included.
+  pub close: Span,
+  /// Whether the tag stands alone on its line, which the parser strips.
+  pub standalone: bool,
+  /// Whether the closing tag stands alone on its line.
+  pub close_standalone: bool,
 }
 
 pub struct DelimiterTag<'a> {
   pub start: &'a str,
   pub stop: &'a str,
+  /// The tag that set these delimiters, or an empty span at the start of the
+  /// template for the ones it began with.
+  pub span: Span,
+  /// Whether the tag stands alone on its line, which the parser strips.
+  pub standalone: bool,
 }
 
 pub enum Tag<'a> {
@@ -27,12 +94,275 @@ pub enum Tag<'a> {
   Inverted(ContextTag<'a>),
   Section(ContextTag<'a>),
   Comment(ValueTag<'a>),
-  Partial(ValueTag<'a>),
+  Partial(PartialTag<'a>),
+  Parent(ParentTag<'a>),
+  Block(BlockTag<'a>),
   Delimiters(DelimiterTag<'a>),
 }
 
+impl<'a> Tag<'a> {
+  /// The source the tag covers, from its opening delimiter through to the
+  /// end of its closing tag, if it has one.
+  pub fn span(&self) -> Span {
+    match self {
+      Tag::Text(tag) | Tag::Comment(tag) => tag.span,
+      Tag::Variable(tag) | Tag::Unescaped(tag) => tag.span,
+      Tag::Inverted(tag) | Tag::Section(tag) => tag.span.to(tag.close),
+      Tag::Partial(tag) => tag.span,
+      Tag::Parent(tag) => tag.span.to(tag.close),
+      Tag::Block(tag) => tag.span.to(tag.close),
+      Tag::Delimiters(tag) => tag.span,
+    }
+  }
+
+  /// Whether the tag, or the opening tag of a section, stands alone on its
+  /// line.
+  pub fn standalone(&self) -> bool {
+    match self {
+      Tag::Text(tag) | Tag::Comment(tag) => tag.standalone,
+      Tag::Variable(_) | Tag::Unescaped(_) => false,
+      Tag::Inverted(tag) | Tag::Section(tag) => tag.standalone,
+      Tag::Partial(tag) => tag.standalone,
+      Tag::Parent(tag) => tag.standalone,
+      Tag::Block(tag) => tag.standalone,
+      Tag::Delimiters(tag) => tag.standalone,
+    }
+  }
+}
+
 pub struct Template<'a> {
   pub tags: Vec<Tag<'a>>,
+  /// Escaping for this template's `{{name}}` tags, and those of the partials
+  /// it includes, in place of the render options' escaper.
+  pub escaper: Option<&'a dyn Escaper>,
+}
+
+/// A location in template source. Lines and columns count from one, and
+/// columns count characters rather than bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+  pub offset: usize,
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Position {
+  pub fn locate(source: &str, offset: usize) -> Position {
+    let lead = &source[..offset];
+    let line_start = lead.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+      offset,
+      line: lead.matches('\n').count() + 1,
+      column: lead[line_start..].chars().count() + 1,
+    }
+  }
+}
+
+/// A stretch of template source, from `start` up to but not including `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+  pub start: Position,
+  pub end: Position,
+}
+
+impl Span {
+  /// The byte range the span covers in the source.
+  pub fn range(&self) -> Range<usize> {
+    self.start.offset..self.end.offset
+  }
+
+  /// The span from the start of this one to the end of another.
+  pub fn to(&self, other: Span) -> Span {
+    Span {
+      start: self.start,
+      end: other.end,
+    }
+  }
+}
+
+impl fmt::Display for Position {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+/// What a render warned was missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Missed {
+  Key,
+  Partial,
+}
+
+/// A key or partial that resolved to nothing during a render.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+  pub missed: Missed,
+  /// The key, or the partial's name.
+  pub key: String,
+  pub position: Position,
+  /// The partial holding the tag, when it is not in the template itself.
+  pub partial: Option<String>,
+}
+
+impl fmt::Display for Warning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(partial) = &self.partial {
+      write!(f, "in partial '{}': ", partial)?;
+    }
+    match self.missed {
+      Missed::Key => write!(f, "{}: key '{}' not found", self.position, self.key),
+      Missed::Partial => write!(f, "{}: partial '{}' not found", self.position, self.key),
+    }
+  }
+}
+
+/// Rendered output, along with any warnings the render raised.
+#[derive(Debug)]
+pub struct Rendered {
+  pub output: String,
+  pub warnings: Vec<Warning>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+  /// A tag whose closing delimiter never appears.
+  UnclosedTag { position: Position, delimiter: String },
+  /// A section or inverted section that is never closed.
+  UnclosedSection { position: Position, key: String },
+  /// A closing tag whose key differs from the section it closes.
+  MismatchedClose {
+    position: Position,
+    open: String,
+    close: String,
+  },
+  /// A closing tag with no section left to close.
+  UnopenedClose { position: Position, key: String },
+  /// A set delimiter tag without a valid pair of delimiters.
+  BadDelimiter { position: Position },
+  /// A tag without a valid key.
+  BadKey { position: Position },
+  /// A key that resolved to nothing in a strict render.
+  MissingKey { position: Position, key: String },
+  /// A partial that the loader could not provide, in a strict render.
+  MissingPartial { position: Position, name: String },
+  /// Partials nested deeper than the render allows, at the tag in the
+  /// template being rendered that began the nesting.
+  DepthExceeded {
+    position: Position,
+    name: String,
+    depth: usize,
+  },
+  /// Partials that include each other outside of any section, which could
+  /// only ever recurse forever. Names the partials around the cycle, and is
+  /// at the tag in the template being checked that leads into it.
+  PartialCycle { position: Position, names: Vec<String> },
+  /// A partial the loader failed to read, at the tag including it.
+  Load {
+    position: Position,
+    name: String,
+    error: io::Error,
+  },
+  /// An error within a partial, at the tag including it. The inner error is
+  /// positioned in the partial's own source.
+  Partial {
+    position: Position,
+    name: String,
+    error: Box<Error>,
+  },
+  /// A precompiled template that could not be encoded or decoded.
+  Format { message: String },
+  /// A precompiled template written by an incompatible version.
+  FormatVersion { version: u32 },
+  /// Data that could not be serialized into a render context.
+  Serialize(serde_json::Error),
+  /// The writer refused the rendered output.
+  Write(io::Error),
+}
+
+impl Error {
+  /// Where in its template the error is, for errors that have a place.
+  pub fn position(&self) -> Option<Position> {
+    match self {
+      Error::UnclosedTag { position, .. }
+      | Error::UnclosedSection { position, .. }
+      | Error::MismatchedClose { position, .. }
+      | Error::UnopenedClose { position, .. }
+      | Error::BadDelimiter { position }
+      | Error::BadKey { position }
+      | Error::MissingKey { position, .. }
+      | Error::MissingPartial { position, .. }
+      | Error::DepthExceeded { position, .. }
+      | Error::PartialCycle { position, .. }
+      | Error::Load { position, .. }
+      | Error::Partial { position, .. } => Some(*position),
+      _ => None,
+    }
+  }
+
+  /// What went wrong without where, for tools that show the position apart.
+  pub fn message(&self) -> String {
+    Message(self).to_string()
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(position) = self.position() {
+      write!(f, "{}: ", position)?;
+    }
+    write!(f, "{}", Message(self))
+  }
+}
+
+/// An error's description, without its position.
+struct Message<'e>(&'e Error);
+
+impl fmt::Display for Message<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.0 {
+      Error::UnclosedTag { delimiter, .. } => write!(f, "tag is never closed with '{}'", delimiter),
+      Error::UnclosedSection { key, .. } => write!(f, "section '{}' is never closed", key),
+      Error::MismatchedClose { open, close, .. } => write!(f, "section '{}' is closed by '{}'", open, close),
+      Error::UnopenedClose { key, .. } => write!(f, "'{}' closes a section that was never opened", key),
+      Error::BadDelimiter { .. } => write!(f, "invalid delimiters"),
+      Error::BadKey { .. } => write!(f, "invalid key"),
+      Error::MissingKey { key, .. } => write!(f, "key '{}' not found", key),
+      Error::MissingPartial { name, .. } => write!(f, "partial '{}' not found", name),
+      Error::DepthExceeded { name, depth, .. } => {
+        write!(f, "partial '{}' is nested more than {} deep", name, depth)
+      }
+      Error::PartialCycle { names, .. } => write!(f, "partials include each other forever: {}", names.join(" -> ")),
+      Error::Load { name, error, .. } => write!(f, "partial '{}' could not be loaded: {}", name, error),
+      Error::Partial { name, error, .. } => write!(f, "in partial '{}': {}", name, error),
+      Error::Format { message } => write!(f, "invalid precompiled template: {}", message),
+      Error::FormatVersion { version } => write!(
+        f,
+        "precompiled template has format version {}, expected {}",
+        version,
+        crate::owned::FORMAT_VERSION
+      ),
+      Error::Serialize(err) => write!(f, "context could not be serialized: {}", err),
+      Error::Write(err) => write!(f, "write failed: {}", err),
+    }
+  }
+}
+
+impl error::Error for Error {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      Error::Load { error, .. } => Some(error),
+      Error::Partial { error, .. } => Some(error.as_ref()),
+      Error::Serialize(err) => Some(err),
+      Error::Write(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for Error {
+  fn from(err: io::Error) -> Error {
+    Error::Write(err)
+  }
 }
 
-pub type Result<T> = std::result::Result<T, String>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, Error>;
\ No newline at end of file
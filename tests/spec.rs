@@ -3,12 +3,14 @@ extern crate mustache_core;
 #[cfg(test)]
 mod spec {
   use macros::test_spec;
-  use mustache_core::{render, Value};
+  use mustache_core::{render, render_with, Lambdas, Options, Value};
   use serde::Deserialize;
+  use std::cell::Cell;
   use std::collections::HashMap;
 
   #[derive(Deserialize)]
   struct MustacheInput {
+    name: String,
     desc: String,
     data: Value,
     template: String,
@@ -22,15 +24,62 @@ mod spec {
     "spec/interpolation.yml",
     "spec/inverted.yml",
     "spec/partials.yml",
-    "spec/sections.yml"
+    "spec/sections.yml",
+    "spec/~inheritance.yml"
   )]
   fn base_test(input: MustacheInput) {
     let partials = input.partials.unwrap_or_default();
-    match render(&input.template, &input.data, |key| {
+    match render(&input.template, &input.data, |key: &str| {
       partials.get(key).cloned()
     }) {
       Ok(actual) => assert_eq!(actual, input.expected, "Spec Panic: {}", &input.desc),
       Err(err) => panic!("Render Panic: {}", err),
     };
   }
+
+  fn spec_lambdas(name: &str) -> Lambdas {
+    let lambdas = Lambdas::new();
+    match name {
+      "Interpolation" => lambdas.interpolation("lambda", || "world".to_string()),
+      "Interpolation - Expansion" => lambdas.interpolation("lambda", || "{{planet}}".to_string()),
+      "Interpolation - Alternate Delimiters" => {
+        lambdas.interpolation("lambda", || "|planet| => {{planet}}".to_string())
+      }
+      "Interpolation - Multiple Calls" => {
+        let calls = Cell::new(0);
+        lambdas.interpolation("lambda", move || {
+          calls.set(calls.get() + 1);
+          calls.get().to_string()
+        })
+      }
+      "Escaping" => lambdas.interpolation("lambda", || ">".to_string()),
+      "Section" => lambdas.section("lambda", |text| {
+        if text == "{{x}}" { "yes" } else { "no" }.to_string()
+      }),
+      "Section - Expansion" => lambdas.section("lambda", |text| format!("{}{{{{planet}}}}{}", text, text)),
+      "Section - Alternate Delimiters" => {
+        lambdas.section("lambda", |text| format!("{}{{{{planet}}}} => |planet|{}", text, text))
+      }
+      "Section - Multiple Calls" => lambdas.section("lambda", |text| format!("__{}__", text)),
+      "Inverted Section" => lambdas.section("lambda", |_| "false".to_string()),
+      _ => panic!("No lambda for spec: {}", name),
+    }
+  }
+
+  #[test_spec("spec/~lambdas.yml")]
+  fn lambda_test(input: MustacheInput) {
+    let partials = input.partials.unwrap_or_default();
+    let lambdas = spec_lambdas(&input.name);
+    // The spec's `lambda` entries hold code in other languages, which the
+    // lambdas above stand in for.
+    let mut data = input.data;
+    if let Value::Object(object) = &mut data {
+      object.remove("lambda");
+    }
+    let options = Options::new().lambdas(&lambdas);
+    match render_with(&input.template, &data, |key: &str| partials.get(key).cloned(), &options) {
+      Ok(actual) => assert_eq!(actual.output, input.expected, "Spec Panic: {}", &input.desc),
+      Err(err) => panic!("Render Panic: {}", err),
+    };
+  }
 }